@@ -0,0 +1,403 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! An access control wrapper for BRBDataTypes.
+//!
+//! AccessControlled enforces an ACL over the ops of an inner data type. Ops are sorted
+//! into classes by an OpClassifier, and actors are granted the classes of ops they may
+//! submit. Admins may submit any op and are the only actors who may change the ACL.
+//!
+//! The ACL is itself changed by ops that go through BRB, so all replicas agree on it.
+//! Permissions are only checked when ops are validated, i.e. against the ACL of the
+//! procs signing the op. An op that gathered its signatures is delivered even if its
+//! source lost its permission in the meantime, since BRB does not order it with the
+//! revocation and replicas would otherwise disagree on its outcome.
+//!
+//! Each admin role and grant is a versioned entry, and each ACL change carries a version
+//! one greater than the version of the entry it replaces. Replicas resolve concurrent
+//! changes to the same entry by keeping the highest version, removals winning ties, so
+//! all replicas converge regardless of the order in which they deliver ops. Changes to
+//! different entries are not checked against each other, e.g. two admins concurrently
+//! removing each other leave the ACL without admins on every replica.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::brb_data_type::{BRBDataType, ValidationContext};
+
+/// Sorts the ops of a data type into classes that permissions are granted on.
+pub trait OpClassifier<Op> {
+    /// A class of ops.
+    type Class: Debug + Clone + Ord + Hash + Serialize;
+
+    /// the class of an op.
+    fn classify(op: &Op) -> Self::Class;
+}
+
+/// Puts every op in the same class, i.e. actors may submit any op or none.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AllOps;
+
+impl<Op> OpClassifier<Op> for AllOps {
+    type Class = ();
+
+    fn classify(_op: &Op) -> Self::Class {}
+}
+
+/// An op on an AccessControlled data type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AclOp<A, Class, Op> {
+    /// An op for the inner data type.
+    Op(Op),
+    /// Make an actor an admin.
+    AddAdmin {
+        /// the actor to make an admin
+        actor: A,
+        /// version of the actor's admin entry after this op
+        version: u64,
+    },
+    /// Take away an actor's admin role.
+    RemoveAdmin {
+        /// the actor to take the admin role from
+        actor: A,
+        /// version of the actor's admin entry after this op
+        version: u64,
+    },
+    /// Allow an actor to submit a class of ops.
+    Grant {
+        /// the actor to grant the permission to
+        actor: A,
+        /// the class of ops
+        class: Class,
+        /// version of the grant entry after this op
+        version: u64,
+    },
+    /// Disallow an actor from submitting a class of ops.
+    Revoke {
+        /// the actor to revoke the permission from
+        actor: A,
+        /// the class of ops
+        class: Class,
+        /// version of the grant entry after this op
+        version: u64,
+    },
+}
+
+/// The reasons an AccessControlled data type refuses an op.
+#[derive(Error, Debug)]
+pub enum AclError<E: Error + 'static> {
+    /// Only admins may change the ACL.
+    #[error("source is not an admin")]
+    NotAnAdmin,
+    /// The source has not been granted this class of ops.
+    #[error("source is not permitted to submit this class of ops")]
+    NotPermitted,
+    /// The last admin may not be removed, the ACL could never change again.
+    #[error("the last admin can not be removed")]
+    LastAdmin,
+    /// The op does not follow the current version of the entry it changes.
+    #[error("op version {version} does not follow the current version {current}")]
+    WrongVersion {
+        /// version of the op
+        version: u64,
+        /// version of the entry in our ACL
+        current: u64,
+    },
+    /// The inner data type refused the op.
+    #[error("inner data type refused op: {0}")]
+    DataType(#[source] E),
+}
+
+/// A versioned admin role or grant, a removed one is kept as a tombstone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AclEntry {
+    /// true if the role or grant is held
+    pub held: bool,
+    /// the number of changes to this entry
+    pub version: u64,
+}
+
+impl AclEntry {
+    /// true if this entry replaces `other`, the highest version wins and removals win ties.
+    fn wins_over(&self, other: &AclEntry) -> bool {
+        (self.version, !self.held) > (other.version, !other.held)
+    }
+}
+
+/// The admins and the classes of ops each actor has been granted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Acl<A: Ord, Class: Ord> {
+    /// the admin entry of each actor, admins may change the ACL and submit any op
+    pub admins: BTreeMap<A, AclEntry>,
+    /// the entry of each class of ops granted to each actor
+    pub grants: BTreeMap<(A, Class), AclEntry>,
+}
+
+impl<A: Ord, Class: Ord> Default for Acl<A, Class> {
+    fn default() -> Self {
+        Acl {
+            admins: Default::default(),
+            grants: Default::default(),
+        }
+    }
+}
+
+impl<A: Ord + Clone, Class: Ord + Clone> Acl<A, Class> {
+    /// true if the actor is an admin.
+    pub fn is_admin(&self, actor: &A) -> bool {
+        self.admins.get(actor).map(|e| e.held).unwrap_or(false)
+    }
+
+    /// the actors who are admins.
+    pub fn admins(&self) -> BTreeSet<&A> {
+        self.admins
+            .iter()
+            .filter(|(_, e)| e.held)
+            .map(|(actor, _)| actor)
+            .collect()
+    }
+
+    /// true if the actor was granted ops of this class, ignoring admin roles.
+    pub fn is_granted(&self, actor: &A, class: &Class) -> bool {
+        self.grants
+            .get(&(actor.clone(), class.clone()))
+            .map(|e| e.held)
+            .unwrap_or(false)
+    }
+
+    /// true if the actor may submit ops of this class.
+    pub fn is_permitted(&self, actor: &A, class: &Class) -> bool {
+        self.is_admin(actor) || self.is_granted(actor, class)
+    }
+
+    /// the current version of the admin entry of an actor.
+    pub fn admin_version(&self, actor: &A) -> u64 {
+        self.admins.get(actor).map(|e| e.version).unwrap_or(0)
+    }
+
+    /// the current version of the entry granting a class of ops to an actor.
+    pub fn grant_version(&self, actor: &A, class: &Class) -> u64 {
+        self.grants
+            .get(&(actor.clone(), class.clone()))
+            .map(|e| e.version)
+            .unwrap_or(0)
+    }
+}
+
+/// A data type whose ops are subject to an ACL.
+#[derive(Debug)]
+pub struct AccessControlled<A, DT, C = AllOps>
+where
+    A: Ord,
+    DT: BRBDataType<A>,
+    C: OpClassifier<DT::Op>,
+{
+    /// the ACL, changed through AclOp's
+    pub acl: Acl<A, C::Class>,
+    /// the inner data type
+    pub dt: DT,
+}
+
+impl<A, DT, C> AccessControlled<A, DT, C>
+where
+    A: Ord + Clone,
+    DT: BRBDataType<A>,
+    C: OpClassifier<DT::Op>,
+{
+    /// Make an actor an admin without going through BRB.
+    ///
+    /// This is used to bootstrap the ACL, every replica must be given the same admins
+    /// before any op is submitted.
+    pub fn force_admin(&mut self, admin: A) {
+        let entry = AclEntry {
+            held: true,
+            version: 0,
+        };
+        self.acl.admins.insert(admin, entry);
+    }
+
+    /// build an op making an actor an admin.
+    pub fn add_admin(&self, actor: A) -> AclOp<A, C::Class, DT::Op> {
+        let version = self.acl.admin_version(&actor) + 1;
+        AclOp::AddAdmin { actor, version }
+    }
+
+    /// build an op taking away an actor's admin role.
+    pub fn remove_admin(&self, actor: A) -> AclOp<A, C::Class, DT::Op> {
+        let version = self.acl.admin_version(&actor) + 1;
+        AclOp::RemoveAdmin { actor, version }
+    }
+
+    /// build an op allowing an actor to submit a class of ops.
+    pub fn grant(&self, actor: A, class: C::Class) -> AclOp<A, C::Class, DT::Op> {
+        let version = self.acl.grant_version(&actor, &class) + 1;
+        AclOp::Grant {
+            actor,
+            class,
+            version,
+        }
+    }
+
+    /// build an op disallowing an actor from submitting a class of ops.
+    pub fn revoke(&self, actor: A, class: C::Class) -> AclOp<A, C::Class, DT::Op> {
+        let version = self.acl.grant_version(&actor, &class) + 1;
+        AclOp::Revoke {
+            actor,
+            class,
+            version,
+        }
+    }
+
+    fn check_acl(
+        &self,
+        source: &A,
+        op: &AclOp<A, C::Class, DT::Op>,
+    ) -> Result<(), AclError<DT::ValidationError>> {
+        let (version, current) = match op {
+            AclOp::Op(op) => {
+                return if self.acl.is_permitted(source, &C::classify(op)) {
+                    Ok(())
+                } else {
+                    Err(AclError::NotPermitted)
+                };
+            }
+            _ if !self.acl.is_admin(source) => return Err(AclError::NotAnAdmin),
+            AclOp::RemoveAdmin { actor, .. }
+                if self.acl.is_admin(actor) && self.acl.admins().len() == 1 =>
+            {
+                return Err(AclError::LastAdmin);
+            }
+            AclOp::AddAdmin { actor, version } | AclOp::RemoveAdmin { actor, version } => {
+                (*version, self.acl.admin_version(actor))
+            }
+            AclOp::Grant {
+                actor,
+                class,
+                version,
+            }
+            | AclOp::Revoke {
+                actor,
+                class,
+                version,
+            } => (*version, self.acl.grant_version(actor, class)),
+        };
+        if version != current + 1 {
+            Err(AclError::WrongVersion { version, current })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn apply_acl_op(&mut self, op: AclOp<A, C::Class, DT::Op>) {
+        match op {
+            AclOp::Op(_) => (),
+            AclOp::AddAdmin { actor, version } => {
+                apply_entry(&mut self.acl.admins, actor, true, version)
+            }
+            AclOp::RemoveAdmin { actor, version } => {
+                apply_entry(&mut self.acl.admins, actor, false, version)
+            }
+            AclOp::Grant {
+                actor,
+                class,
+                version,
+            } => apply_entry(&mut self.acl.grants, (actor, class), true, version),
+            AclOp::Revoke {
+                actor,
+                class,
+                version,
+            } => apply_entry(&mut self.acl.grants, (actor, class), false, version),
+        }
+    }
+}
+
+/// Keep the winner of an entry and a concurrent change to it.
+fn apply_entry<K: Ord>(entries: &mut BTreeMap<K, AclEntry>, key: K, held: bool, version: u64) {
+    let entry = AclEntry { held, version };
+    match entries.get(&key) {
+        Some(current) if !entry.wins_over(current) => (),
+        _ => {
+            entries.insert(key, entry);
+        }
+    }
+}
+
+impl<A, DT, C> BRBDataType<A> for AccessControlled<A, DT, C>
+where
+    A: Ord + Clone + Debug + Hash + Serialize,
+    DT: BRBDataType<A>,
+    C: OpClassifier<DT::Op> + Debug,
+{
+    type Op = AclOp<A, C::Class, DT::Op>;
+    type ValidationError = AclError<DT::ValidationError>;
+
+    fn new(actor: A) -> Self {
+        AccessControlled {
+            acl: Default::default(),
+            dt: DT::new(actor),
+        }
+    }
+
+    fn validate(&self, source: &A, op: &Self::Op) -> Result<(), Self::ValidationError> {
+        self.check_acl(source, op)?;
+        match op {
+            AclOp::Op(op) => self.dt.validate(source, op).map_err(AclError::DataType),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_with_context(
+        &self,
+        ctx: &ValidationContext<A>,
+        op: &Self::Op,
+    ) -> Result<(), Self::ValidationError>
+    where
+        A: Ord,
+    {
+        self.check_acl(ctx.source, op)?;
+        match op {
+            AclOp::Op(op) => self
+                .dt
+                .validate_with_context(ctx, op)
+                .map_err(AclError::DataType),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(&mut self, op: Self::Op) {
+        match op {
+            AclOp::Op(op) => self.dt.apply(op),
+            op => self.apply_acl_op(op),
+        }
+    }
+
+    fn try_apply(
+        &mut self,
+        ctx: &ValidationContext<A>,
+        op: Self::Op,
+    ) -> Result<(), Self::ValidationError>
+    where
+        A: Ord,
+    {
+        // The ACL is not checked again, the op was permitted by the procs that signed it
+        // and replicas may deliver it before or after concurrent ACL changes.
+        match op {
+            AclOp::Op(op) => self.dt.try_apply(ctx, op).map_err(AclError::DataType),
+            op => {
+                self.apply_acl_op(op);
+                Ok(())
+            }
+        }
+    }
+}
@@ -16,12 +16,34 @@
 //! Examples of types that implement BRBDataType:
 //!   brb_dt_orswot, brb_dt_at2, brb_dt_tree
 
+use std::collections::BTreeSet;
 use std::error::Error;
 use std::fmt::Debug;
 use std::hash::Hash;
 
+use brb_membership::Generation;
+use crdts::{Dot, VClock};
 use serde::Serialize;
 
+/// The context in which an op is being validated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationContext<'a, A: Ord> {
+    /// the actor that proposed the op
+    pub source: &'a A,
+    /// dot of the msg carrying the op
+    pub dot: &'a Dot<A>,
+    /// generation of the msg carrying the op
+    pub gen: Generation,
+    /// the voting members of the msg's generation
+    pub members: &'a BTreeSet<A>,
+    /// the clock of msgs we delivered before this one.
+    ///
+    /// This is our local view: msgs from different sources are not ordered by BRB, so
+    /// other replicas may have delivered a different set of them by now. Only the entry
+    /// of `source` is the same on every replica.
+    pub delivered: &'a VClock<A>,
+}
+
 /// The BRBDataType trait
 pub trait BRBDataType<A>: Debug {
     /// The set of ops this data type accepts
@@ -38,6 +60,46 @@ pub trait BRBDataType<A>: Debug {
     /// tolerance checks specific to your algorithm    
     fn validate(&self, source: &A, op: &Self::Op) -> Result<(), Self::ValidationError>;
 
+    /// Validate an incoming operation given the context it was proposed in, e.g. the
+    /// dot and generation of its msg and the current voting members.
+    ///
+    /// This is what BRB calls, by default it ignores the context and defers to `validate`.
+    /// Data types that need the context should override it.
+    fn validate_with_context(
+        &self,
+        ctx: &ValidationContext<A>,
+        op: &Self::Op,
+    ) -> Result<(), Self::ValidationError>
+    where
+        A: Ord,
+    {
+        self.validate(ctx.source, op)
+    }
+
     /// Execute an op after it has been validated.
     fn apply(&mut self, op: Self::Op);
+
+    /// Execute an op once the network has agreed to deliver it.
+    ///
+    /// The state of the data type may have moved on since the op was validated, this
+    /// is the last chance to re-validate it and refuse to apply it. Since every replica
+    /// records the outcome in its history, the decision must be deterministic, i.e. it
+    /// may only depend on the op, its dot and generation, the members of that generation
+    /// and earlier ops from the same source. BRB delivers ops from different sources in
+    /// any order, so state they changed and the other entries of `ctx.delivered` may
+    /// differ between replicas.
+    ///
+    /// By default the op is always applied.
+    fn try_apply(
+        &mut self,
+        ctx: &ValidationContext<A>,
+        op: Self::Op,
+    ) -> Result<(), Self::ValidationError>
+    where
+        A: Ord,
+    {
+        let _ = ctx;
+        self.apply(op);
+        Ok(())
+    }
 }
@@ -0,0 +1,193 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! A generic adapter for running any `crdts::CmRDT` over BRB.
+//!
+//! BRBCmRDT implements BRBDataType for a CmRDT, validating incoming ops with the
+//! CRDT's own `validate_op` along with a ValidationPolicy deciding which sources
+//! may submit which ops.
+//!
+//! Not every CmRDT op implements `Hash`, which BRB needs, so ops are wrapped in CmRDTOp.
+
+use std::error::Error;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+
+use crdts::{map, orswot, pncounter, CmRDT, Dot};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::brb_data_type::BRBDataType;
+
+/// A CmRDT op, as carried by BRB.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CmRDTOp<O>(pub O);
+
+impl<O> From<O> for CmRDTOp<O> {
+    fn from(op: O) -> Self {
+        CmRDTOp(op)
+    }
+}
+
+impl<O: Serialize> Hash for CmRDTOp<O> {
+    /// Ops are hashed by their serialized bytes, equal ops must serialize identically.
+    /// This already holds for the ops in `crdts` and is relied on when signing msgs.
+    ///
+    /// Hash can't fail, so an op that fails to serialize is hashed by its error instead.
+    /// Such an op can't be signed either, `exec_op` refuses it with `Error::Encoding`.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match bincode::serialize(&self.0) {
+            Ok(bytes) => bytes.hash(state),
+            Err(err) => err.to_string().hash(state),
+        }
+    }
+}
+
+/// Ops that know which actors created the state they touch.
+pub trait AuthoredOp<A> {
+    /// the actors whose dots this op adds or removes.
+    fn authors(&self) -> Vec<&A>;
+}
+
+impl<A> AuthoredOp<A> for Dot<A> {
+    fn authors(&self) -> Vec<&A> {
+        vec![&self.actor]
+    }
+}
+
+impl<A: Ord> AuthoredOp<A> for pncounter::Op<A> {
+    fn authors(&self) -> Vec<&A> {
+        vec![&self.dot.actor]
+    }
+}
+
+impl<M, A: Ord> AuthoredOp<A> for orswot::Op<M, A> {
+    fn authors(&self) -> Vec<&A> {
+        match self {
+            orswot::Op::Add { dot, .. } => vec![&dot.actor],
+            orswot::Op::Rm { clock, .. } => clock.iter().map(|dot| dot.actor).collect(),
+        }
+    }
+}
+
+impl<K: Ord, V: map::Val<A>, A: Ord> AuthoredOp<A> for map::Op<K, V, A> {
+    fn authors(&self) -> Vec<&A> {
+        match self {
+            map::Op::Up { dot, .. } => vec![&dot.actor],
+            map::Op::Rm { clock, .. } => clock.iter().map(|dot| dot.actor).collect(),
+        }
+    }
+}
+
+/// Decides whether a source may submit an op to a CmRDT.
+pub trait ValidationPolicy<A, C: CmRDT>: Debug + Default {
+    /// The error returned when an op is refused.
+    type Error: Debug + Error + 'static;
+
+    /// Validate an op submitted by `source` against the current state of the CRDT.
+    fn validate(&self, source: &A, crdt: &C, op: &C::Op) -> Result<(), Self::Error>;
+}
+
+/// A policy accepting any op from any source.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AcceptAll;
+
+/// AcceptAll never refuses an op.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("unreachable")]
+pub struct Unreachable;
+
+impl<A, C: CmRDT> ValidationPolicy<A, C> for AcceptAll {
+    type Error = Unreachable;
+
+    fn validate(&self, _source: &A, _crdt: &C, _op: &C::Op) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A policy only accepting ops authored by the source that submitted them.
+/// A remove is only accepted if every dot it removes was authored by its source,
+/// i.e. actors may only remove what they added themselves.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SourceIsAuthor;
+
+/// The op was authored by an actor other than its source.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("op was authored by an actor other than its source")]
+pub struct NotAuthoredBySource;
+
+impl<A: PartialEq, C: CmRDT> ValidationPolicy<A, C> for SourceIsAuthor
+where
+    C::Op: AuthoredOp<A>,
+{
+    type Error = NotAuthoredBySource;
+
+    fn validate(&self, source: &A, _crdt: &C, op: &C::Op) -> Result<(), Self::Error> {
+        if op.authors().into_iter().all(|author| author == source) {
+            Ok(())
+        } else {
+            Err(NotAuthoredBySource)
+        }
+    }
+}
+
+/// The reasons a BRBCmRDT may refuse an op.
+#[derive(Error, Debug)]
+pub enum CmRDTValidationError<C: Error + 'static, P: Error + 'static> {
+    /// The op is not valid for the CRDT.
+    #[error("op failed CRDT validation: {0}")]
+    Crdt(#[source] C),
+    /// The op was refused by the validation policy.
+    #[error("op was refused by the validation policy: {0}")]
+    Policy(#[source] P),
+}
+
+/// A CmRDT running over BRB.
+#[derive(Debug)]
+pub struct BRBCmRDT<A, C, P = AcceptAll> {
+    /// the actor of this replica, for generating ops
+    pub actor: A,
+    /// the underlying CRDT
+    pub crdt: C,
+    /// the policy used to validate incoming ops
+    pub policy: P,
+}
+
+impl<A, C, P> BRBDataType<A> for BRBCmRDT<A, C, P>
+where
+    A: Debug,
+    C: CmRDT + Debug + Default,
+    C::Op: Debug + Clone + Eq + Serialize,
+    C::Validation: 'static,
+    P: ValidationPolicy<A, C>,
+{
+    type Op = CmRDTOp<C::Op>;
+    type ValidationError = CmRDTValidationError<C::Validation, P::Error>;
+
+    fn new(actor: A) -> Self {
+        BRBCmRDT {
+            actor,
+            crdt: Default::default(),
+            policy: Default::default(),
+        }
+    }
+
+    fn validate(&self, source: &A, op: &Self::Op) -> Result<(), Self::ValidationError> {
+        self.policy
+            .validate(source, &self.crdt, &op.0)
+            .map_err(CmRDTValidationError::Policy)?;
+        self.crdt
+            .validate_op(&op.0)
+            .map_err(CmRDTValidationError::Crdt)
+    }
+
+    fn apply(&mut self, op: Self::Op) {
+        self.crdt.apply(op.0);
+    }
+}
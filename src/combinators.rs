@@ -0,0 +1,366 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Combinators for securing several independent data types with a single BRB group.
+//!
+//! Product pairs up two data types, dispatching each op to one of them.
+//!
+//! Registry hosts any number of data types of different types by name. Ops are carried
+//! serialized, so registered data types must have deserializable ops, and every replica
+//! must register the same data types under the same names.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Debug;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::brb_data_type::{BRBDataType, ValidationContext};
+
+/// An op for one side of a Product.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ProductOp<L, R> {
+    /// an op for the left data type
+    Left(L),
+    /// an op for the right data type
+    Right(R),
+}
+
+/// An error from one side of a Product.
+#[derive(Error, Debug)]
+pub enum ProductError<L: Error + 'static, R: Error + 'static> {
+    /// the left data type refused the op
+    #[error("left data type refused op: {0}")]
+    Left(#[source] L),
+    /// the right data type refused the op
+    #[error("right data type refused op: {0}")]
+    Right(#[source] R),
+}
+
+/// Two data types secured by the same BRB group.
+#[derive(Debug)]
+pub struct Product<L, R> {
+    /// the left data type
+    pub left: L,
+    /// the right data type
+    pub right: R,
+}
+
+impl<A: Clone, L: BRBDataType<A>, R: BRBDataType<A>> BRBDataType<A> for Product<L, R> {
+    type Op = ProductOp<L::Op, R::Op>;
+    type ValidationError = ProductError<L::ValidationError, R::ValidationError>;
+
+    fn new(actor: A) -> Self {
+        Product {
+            left: L::new(actor.clone()),
+            right: R::new(actor),
+        }
+    }
+
+    fn validate(&self, source: &A, op: &Self::Op) -> Result<(), Self::ValidationError> {
+        match op {
+            ProductOp::Left(op) => self.left.validate(source, op).map_err(ProductError::Left),
+            ProductOp::Right(op) => self.right.validate(source, op).map_err(ProductError::Right),
+        }
+    }
+
+    fn validate_with_context(
+        &self,
+        ctx: &ValidationContext<A>,
+        op: &Self::Op,
+    ) -> Result<(), Self::ValidationError>
+    where
+        A: Ord,
+    {
+        match op {
+            ProductOp::Left(op) => self
+                .left
+                .validate_with_context(ctx, op)
+                .map_err(ProductError::Left),
+            ProductOp::Right(op) => self
+                .right
+                .validate_with_context(ctx, op)
+                .map_err(ProductError::Right),
+        }
+    }
+
+    fn apply(&mut self, op: Self::Op) {
+        match op {
+            ProductOp::Left(op) => self.left.apply(op),
+            ProductOp::Right(op) => self.right.apply(op),
+        }
+    }
+
+    fn try_apply(
+        &mut self,
+        ctx: &ValidationContext<A>,
+        op: Self::Op,
+    ) -> Result<(), Self::ValidationError>
+    where
+        A: Ord,
+    {
+        match op {
+            ProductOp::Left(op) => self.left.try_apply(ctx, op).map_err(ProductError::Left),
+            ProductOp::Right(op) => self.right.try_apply(ctx, op).map_err(ProductError::Right),
+        }
+    }
+}
+
+/// An op for the data type registered under `name`, serialized with bincode.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RegistryOp {
+    /// name of the data type the op is for
+    pub name: String,
+    /// the serialized op
+    pub op: Vec<u8>,
+}
+
+/// The reasons a Registry refuses an op.
+#[derive(Error, Debug)]
+pub enum RegistryError {
+    /// No data type is registered under this name.
+    #[error("no data type is registered as {name:?}")]
+    UnknownDataType {
+        /// name of the data type
+        name: String,
+    },
+    /// The op could not be deserialized for the data type.
+    #[error("op is malformed for data type {name:?}")]
+    MalformedOp {
+        /// name of the data type
+        name: String,
+    },
+    /// The op could not be serialized for the data type.
+    #[error("failed to serialize op for data type {name:?}: {source}")]
+    Serialize {
+        /// name of the data type
+        name: String,
+        /// the error returned by bincode
+        source: bincode::Error,
+    },
+    /// The data type refused the op.
+    #[error("data type {name:?} refused op: {source}")]
+    DataType {
+        /// name of the data type
+        name: String,
+        /// the error returned by the data type
+        source: Box<dyn Error + Send + Sync>,
+    },
+}
+
+/// A data type with its ops erased to bytes.
+trait ErasedDataType<A: Ord>: Debug {
+    fn validate(&self, source: &A, op: &[u8]) -> Result<(), RegistryError>;
+
+    fn validate_with_context(
+        &self,
+        ctx: &ValidationContext<A>,
+        op: &[u8],
+    ) -> Result<(), RegistryError>;
+
+    fn apply(&mut self, op: &[u8]);
+
+    fn try_apply(&mut self, ctx: &ValidationContext<A>, op: &[u8]) -> Result<(), RegistryError>;
+
+    fn name(&self) -> &str;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+#[derive(Debug)]
+struct Registered<DT> {
+    name: String,
+    dt: DT,
+}
+
+impl<DT> Registered<DT> {
+    fn decode<Op: DeserializeOwned>(&self, op: &[u8]) -> Result<Op, RegistryError> {
+        bincode::deserialize(op).map_err(|_| RegistryError::MalformedOp {
+            name: self.name.clone(),
+        })
+    }
+
+    fn refused<E: Error + Send + Sync + 'static>(&self, err: E) -> RegistryError {
+        RegistryError::DataType {
+            name: self.name.clone(),
+            source: Box::new(err),
+        }
+    }
+}
+
+impl<A, DT> ErasedDataType<A> for Registered<DT>
+where
+    A: Ord,
+    DT: BRBDataType<A> + 'static,
+    DT::Op: DeserializeOwned,
+    DT::ValidationError: Send + Sync,
+{
+    fn validate(&self, source: &A, op: &[u8]) -> Result<(), RegistryError> {
+        let op = self.decode(op)?;
+        self.dt
+            .validate(source, &op)
+            .map_err(|err| self.refused(err))
+    }
+
+    fn validate_with_context(
+        &self,
+        ctx: &ValidationContext<A>,
+        op: &[u8],
+    ) -> Result<(), RegistryError> {
+        let op = self.decode(op)?;
+        self.dt
+            .validate_with_context(ctx, &op)
+            .map_err(|err| self.refused(err))
+    }
+
+    fn apply(&mut self, op: &[u8]) {
+        // ops have been validated before they are applied, so they decode.
+        if let Ok(op) = self.decode(op) {
+            self.dt.apply(op);
+        }
+    }
+
+    fn try_apply(&mut self, ctx: &ValidationContext<A>, op: &[u8]) -> Result<(), RegistryError> {
+        let op = self.decode(op)?;
+        match self.dt.try_apply(ctx, op) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(self.refused(err)),
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.dt
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut self.dt
+    }
+}
+
+/// Any number of data types, of different types, secured by the same BRB group.
+#[derive(Debug)]
+pub struct Registry<A: Ord> {
+    /// the actor of this replica, data types are registered with it
+    pub actor: A,
+    data_types: BTreeMap<String, Box<dyn ErasedDataType<A>>>,
+}
+
+impl<A: Ord> Registry<A> {
+    fn registered(&self, name: &str) -> Result<&dyn ErasedDataType<A>, RegistryError> {
+        self.data_types
+            .get(name)
+            .map(|dt| dt.as_ref())
+            .ok_or_else(|| RegistryError::UnknownDataType {
+                name: name.to_string(),
+            })
+    }
+}
+
+impl<A: Ord + Clone + 'static> Registry<A> {
+    /// Register a new data type under `name`, replacing any data type already registered
+    /// under that name.
+    pub fn register<DT>(&mut self, name: impl Into<String>) -> &mut DT
+    where
+        DT: BRBDataType<A> + 'static,
+        DT::Op: DeserializeOwned,
+        DT::ValidationError: Send + Sync,
+    {
+        let name = name.into();
+        let registered = Registered {
+            name: name.clone(),
+            dt: DT::new(self.actor.clone()),
+        };
+        self.data_types.insert(name.clone(), Box::new(registered));
+        self.get_mut(&name)
+            .expect("we just registered this data type")
+    }
+
+    /// the data type registered under `name`, if it is a DT.
+    pub fn get<DT: 'static>(&self, name: &str) -> Option<&DT> {
+        self.data_types.get(name)?.as_any().downcast_ref()
+    }
+
+    /// the data type registered under `name`, if it is a DT.
+    pub fn get_mut<DT: 'static>(&mut self, name: &str) -> Option<&mut DT> {
+        self.data_types.get_mut(name)?.as_any_mut().downcast_mut()
+    }
+
+    /// the names of the registered data types.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.data_types.values().map(|dt| dt.name())
+    }
+
+    /// Build an op for the data type registered under `name`.
+    pub fn op<Op: Serialize>(
+        name: impl Into<String>,
+        op: &Op,
+    ) -> Result<RegistryOp, RegistryError> {
+        let name = name.into();
+        match bincode::serialize(op) {
+            Ok(op) => Ok(RegistryOp { name, op }),
+            Err(source) => Err(RegistryError::Serialize { name, source }),
+        }
+    }
+}
+
+impl<A: Ord + Debug + 'static> BRBDataType<A> for Registry<A> {
+    type Op = RegistryOp;
+    type ValidationError = RegistryError;
+
+    fn new(actor: A) -> Self {
+        Registry {
+            actor,
+            data_types: Default::default(),
+        }
+    }
+
+    fn validate(&self, source: &A, op: &Self::Op) -> Result<(), Self::ValidationError> {
+        self.registered(&op.name)?.validate(source, &op.op)
+    }
+
+    fn validate_with_context(
+        &self,
+        ctx: &ValidationContext<A>,
+        op: &Self::Op,
+    ) -> Result<(), Self::ValidationError>
+    where
+        A: Ord,
+    {
+        self.registered(&op.name)?
+            .validate_with_context(ctx, &op.op)
+    }
+
+    fn apply(&mut self, op: Self::Op) {
+        if let Some(dt) = self.data_types.get_mut(&op.name) {
+            dt.apply(&op.op);
+        }
+    }
+
+    fn try_apply(
+        &mut self,
+        ctx: &ValidationContext<A>,
+        op: Self::Op,
+    ) -> Result<(), Self::ValidationError>
+    where
+        A: Ord,
+    {
+        self.data_types
+            .get_mut(&op.name)
+            .ok_or(RegistryError::UnknownDataType { name: op.name })?
+            .try_apply(ctx, &op.op)
+    }
+}
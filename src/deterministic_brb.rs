@@ -22,8 +22,13 @@
 
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 
-use crate::brb_data_type::BRBDataType;
-use crate::packet::{Packet, Payload};
+use crate::brb_data_type::{BRBDataType, ValidationContext};
+use crate::packet::{Packet, Payload, RejectionReason};
+use crate::rate_limit::{LimitedPayload, RateLimiter};
+use crate::reputation::Reputation;
+
+#[cfg(feature = "metrics")]
+use crate::metrics::{Gauges, Metrics};
 use crate::{Error, ValidationError};
 
 use log::info;
@@ -32,6 +37,19 @@ use brb_membership::{self, Actor, Generation, Sig, SigningActor};
 use crdts::{CmRDT, Dot, VClock};
 use serde::{Deserialize, Serialize};
 
+/// Default for the maximum number of early packets buffered per source.
+pub const DEFAULT_REORDER_BUFFER_LIMIT: usize = 16;
+
+/// Default for the maximum number of early packets buffered across all sources.
+pub const DEFAULT_REORDER_BUFFER_TOTAL_LIMIT: usize = 256;
+
+/// Default for how many generations ahead of our own we will buffer msgs for.
+pub const DEFAULT_GENERATION_LOOKAHEAD: Generation = 1;
+
+/// Default for the minimum number of packets we handle between two catch-up
+/// requests to the same peer.
+pub const DEFAULT_CATCH_UP_COOLDOWN: u64 = 16;
+
 /// DeterministicBRB -- the heart and soul of BRB.
 #[derive(Debug)]
 pub struct DeterministicBRB<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>> {
@@ -46,6 +64,54 @@ pub struct DeterministicBRB<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT:
     #[allow(clippy::type_complexity)]
     pub pending_delivery: HashMap<Msg<A, BRBDT::Op>, (BTreeMap<A, S>, BTreeSet<A>)>,
 
+    /// Msgs we have signed for a source but have not yet seen delivered, along with the
+    /// signature we gave. A source may only have one undelivered msg at a time, so this
+    /// lets us answer a duplicate RequestValidation with the same signature.
+    #[allow(clippy::type_complexity)]
+    pub signed_validations: HashMap<A, (Msg<A, BRBDT::Op>, S)>,
+
+    /// Packets that arrived before we were ready to process them, by packet source.
+    /// These are valid proofs for future dots and validation requests from a near-future
+    /// generation. They are processed as soon as we've caught up to them.
+    pub reorder_buffer: BTreeMap<A, Vec<Packet<A, S, BRBDT::Op>>>,
+
+    /// The maximum number of packets we will hold in the reorder buffer of a source.
+    pub reorder_buffer_limit: usize,
+
+    /// The maximum number of packets we will hold in the reorder buffer across all sources.
+    pub reorder_buffer_total_limit: usize,
+
+    /// How many generations ahead of our own we will buffer msgs for.
+    pub generation_lookahead: Generation,
+
+    /// If true, we send an AntiEntropy request to peers whose packets show that we
+    /// have fallen behind.
+    pub auto_catch_up: bool,
+
+    /// The minimum number of packets we handle between two catch-up requests to the same peer.
+    pub catch_up_cooldown: u64,
+
+    /// When we last sent each peer a catch-up request, measured in packets handled.
+    pub last_catch_up: BTreeMap<A, u64>,
+
+    /// The number of packets we have handled, this is the logical clock used for rate limiting,
+    /// reputation decay and catch-up cooldowns. To keep BRB deterministic, time is measured in
+    /// packets handled rather than wall-clock time.
+    pub packets_handled: u64,
+
+    /// Limits how many expensive packets each peer may send us.
+    pub rate_limiter: RateLimiter<A>,
+
+    /// Reputation of our peers, scored from the packets we reject.
+    pub reputation: Reputation<A>,
+
+    /// Peers we have already proposed to remove due to bad reputation.
+    pub proposed_removals: BTreeSet<A>,
+
+    /// Metrics collected while handling packets.
+    #[cfg(feature = "metrics")]
+    pub metrics: Metrics<A>,
+
     /// The clock representing the most recently received messages from each process.
     /// These are messages that have been acknowledged but not yet
     /// This clock must at all times be greator or equal to the `delivered` clock.
@@ -56,7 +122,7 @@ pub struct DeterministicBRB<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT:
 
     /// History is maintained to onboard new members
     #[allow(clippy::type_complexity)]
-    pub history_from_source: BTreeMap<A, Vec<(Msg<A, BRBDT::Op>, BTreeMap<A, S>)>>,
+    pub history_from_source: BTreeMap<A, Vec<(Msg<A, BRBDT::Op>, BTreeMap<A, S>, DeliveryOutcome)>>,
 
     /// The state of the datatype that we are running BFT over.
     /// This can be the causal bank described in AT2, or it can be a CRDT.
@@ -75,6 +141,16 @@ pub struct Msg<A, DataTypeOp> {
     pub dot: Dot<A>,
 }
 
+/// The effect a delivered msg had on the data type, recorded in history so that
+/// every replica agrees on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeliveryOutcome {
+    /// The op was applied to the data type.
+    Applied,
+    /// The data type refused to apply the op when it was delivered.
+    RejectedByDataType,
+}
+
 /// An enumeration of BRB operations
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Op<A: Ord, S, DataTypeOp> {
@@ -108,11 +184,36 @@ pub enum Op<A: Ord, S, DataTypeOp> {
     },
 }
 
+impl<A: Ord, S, DataTypeOp> Op<A, S, DataTypeOp> {
+    /// returns the msg this op is concerned with
+    pub fn msg(&self) -> &Msg<A, DataTypeOp> {
+        match self {
+            Op::RequestValidation { msg }
+            | Op::SignedValidated { msg, .. }
+            | Op::ProofOfAgreement { msg, .. }
+            | Op::Delivered { msg } => msg,
+        }
+    }
+}
+
 impl<A: Actor<S>, S: Sig, DataTypeOp> Payload<A, S, DataTypeOp> {
     /// true if this Payload represents an Op::ProofOfAgreement
     pub fn is_proof_of_agreement(&self) -> bool {
         matches!(self, Payload::BRB(Op::ProofOfAgreement { .. }))
     }
+
+    /// returns a short name for the kind of this Payload, BRB ops are named by Op variant
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Payload::AntiEntropy { .. } => "anti_entropy",
+            Payload::BRB(Op::RequestValidation { .. }) => "request_validation",
+            Payload::BRB(Op::SignedValidated { .. }) => "signed_validated",
+            Payload::BRB(Op::ProofOfAgreement { .. }) => "proof_of_agreement",
+            Payload::BRB(Op::Delivered { .. }) => "delivered",
+            Payload::Membership(_) => "membership",
+            Payload::Rejected { .. } => "rejected",
+        }
+    }
 }
 
 impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>> Default
@@ -129,13 +230,34 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
 {
     /// returns a new DeterministicBRB
     pub fn new() -> Self {
-        let membership: brb_membership::State<A, SA, S> = Default::default();
+        Self::with_signing_actor(Default::default())
+    }
+
+    /// returns a new DeterministicBRB signing with the given keys
+    #[allow(clippy::field_reassign_with_default)]
+    pub fn with_signing_actor(id: SA) -> Self {
+        let mut membership: brb_membership::State<A, SA, S> = Default::default();
+        membership.id = id;
         let dt = BRBDT::new(membership.id.actor());
         Self {
             membership,
             dt,
             pending_proof: Default::default(),
             pending_delivery: Default::default(),
+            signed_validations: Default::default(),
+            reorder_buffer: Default::default(),
+            reorder_buffer_limit: DEFAULT_REORDER_BUFFER_LIMIT,
+            reorder_buffer_total_limit: DEFAULT_REORDER_BUFFER_TOTAL_LIMIT,
+            generation_lookahead: DEFAULT_GENERATION_LOOKAHEAD,
+            auto_catch_up: false,
+            catch_up_cooldown: DEFAULT_CATCH_UP_COOLDOWN,
+            last_catch_up: Default::default(),
+            packets_handled: 0,
+            rate_limiter: Default::default(),
+            reputation: Default::default(),
+            proposed_removals: Default::default(),
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
             delivered: Default::default(),
             received: Default::default(),
             history_from_source: Default::default(),
@@ -151,7 +273,7 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
     pub fn peers(&self) -> Result<BTreeSet<A>, Error<A, S, BRBDT::ValidationError>> {
         self.membership
             .members(self.membership.gen)
-            .map_err(Error::Membership)
+            .map_err(Error::from)
     }
 
     /// Locally adds a peer to voting group without going through the
@@ -300,7 +422,6 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
     ///
     /// brb.exec_op(op)?;
     /// ```
-
     #[allow(clippy::type_complexity)]
     pub fn exec_op(
         &mut self,
@@ -314,7 +435,13 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
             dot: self.received.inc(self.actor()),
         };
 
+        #[cfg(feature = "tracing")]
+        let _span = self.msg_span("exec_op", &msg);
+
         info!("[BRB] {} initiating bft for msg {:?}", self.actor(), msg);
+        #[cfg(feature = "metrics")]
+        self.metrics.record_exec(msg.dot);
+
         let (mut self_packets, mut others_packets): (Vec<_>, Vec<_>) = self
             .broadcast(&Payload::BRB(Op::RequestValidation { msg }), self.peers()?)?
             .into_iter()
@@ -332,6 +459,11 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
     }
 
     /// handles an incoming BRB Packet.
+    ///
+    /// If `auto_catch_up` is set and the packet shows that we have fallen behind its
+    /// source, we return an AntiEntropy request to the source instead of an error.
+    /// The packet is still counted as rejected in our metrics, but the source is never
+    /// penalized for packets we rejected because we are behind.
     #[allow(clippy::type_complexity)]
     pub fn handle_packet(
         &mut self,
@@ -342,7 +474,383 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
             packet.source,
             self.actor()
         );
+        #[cfg(feature = "metrics")]
+        self.metrics.record_packet(&packet.payload);
+
+        #[cfg(feature = "tracing")]
+        let _span = match &packet.payload {
+            Payload::BRB(op) => Some(self.msg_span(packet.payload.kind(), op.msg())),
+            _ => None,
+        };
+
+        let source = packet.source;
+        let result = self.try_handle_packet(packet);
+
+        if let Err(err) = &result {
+            self.record_rejection(source, err);
+        }
+        result
+    }
+
+    /// Records that a packet from source failed with err.
+    fn record_rejection(&mut self, source: A, err: &Error<A, S, BRBDT::ValidationError>) {
+        #[cfg(feature = "metrics")]
+        self.metrics.record_rejection(err);
+        self.penalize(source, err);
+    }
+
+    /// Updates the reputation of the peer responsible for a packet from source failing with err.
+    fn penalize(&mut self, source: A, err: &Error<A, S, BRBDT::ValidationError>) {
+        let peer = match (err.offender(), err) {
+            // We've already penalized this peer, that's why it's quarantined
+            (_, Error::Quarantined { .. }) => return,
+            // The peer is ahead of us, lagging must not get our honest peers quarantined
+            (_, err) if Self::is_behind(err) => return,
+            (Some(offender), _) => *offender,
+            // The packet may have been forged by someone other than source
+            (None, Error::Signature(_)) => return,
+            (None, _) => source,
+        };
+
+        if peer != self.actor() {
+            let penalty = self
+                .reputation
+                .penalize(peer, err.severity(), self.packets_handled);
+            info!("[BRB] penalized {}, penalty is now {}", peer, penalty);
+        }
+    }
+
+    /// Proposes the removal of peers whose reputation has crossed the configured
+    /// removal threshold. Each peer is only proposed for removal once.
+    ///
+    /// This is meant to be called periodically by the node runtime.
+    #[allow(clippy::type_complexity)]
+    pub fn propose_removals(
+        &mut self,
+    ) -> Result<Vec<Packet<A, S, BRBDT::Op>>, Error<A, S, BRBDT::ValidationError>> {
+        let members = self.peers()?;
+        let peers_to_remove: Vec<A> = self
+            .reputation
+            .peers_to_remove(self.packets_handled)
+            .into_iter()
+            .filter(|peer| members.contains(peer) && !self.proposed_removals.contains(peer))
+            .copied()
+            .collect();
+
+        let mut packets_to_send = Vec::new();
+        for peer in peers_to_remove {
+            info!("[BRB] proposing removal of {} due to bad reputation", peer);
+            packets_to_send.extend(self.kill_peer(peer)?);
+            self.proposed_removals.insert(peer);
+        }
+        Ok(packets_to_send)
+    }
+
+    /// Encodes our metrics in the Prometheus text format.
+    #[cfg(feature = "metrics")]
+    pub fn encode_metrics(&self) -> String {
+        self.metrics.encode(&Gauges {
+            pending_proof: self.pending_proof.len(),
+            pending_delivery: self.pending_delivery.len(),
+            history_len: self.history_from_source.values().map(Vec::len).sum(),
+        })
+    }
+
+    /// Enters a tracing span for a stage in the lifecycle of msg.
+    ///
+    /// Spans are keyed by the msg's dot and generation so that an op can be followed
+    /// from RequestValidation through to Delivered across all nodes. Our `log` output
+    /// is recorded within these spans when `log` records are forwarded to `tracing`.
+    /// `replayed` is set on the spans of packets taken from the reorder buffer.
+    #[cfg(feature = "tracing")]
+    fn msg_span(&self, stage: &'static str, msg: &Msg<A, BRBDT::Op>) -> tracing::span::EnteredSpan {
+        tracing::info_span!(
+            "brb_msg",
+            stage,
+            node = %self.actor(),
+            dot.actor = %msg.dot.actor,
+            dot.counter = msg.dot.counter,
+            gen = msg.gen,
+            replayed = false,
+        )
+        .entered()
+    }
+
+    /// validates, then processes an incoming BRB Packet, see handle_packet.
+    #[allow(clippy::type_complexity)]
+    fn try_handle_packet(
+        &mut self,
+        packet: Packet<A, S, BRBDT::Op>,
+    ) -> Result<Vec<Packet<A, S, BRBDT::Op>>, Error<A, S, BRBDT::ValidationError>> {
+        self.packets_handled += 1;
+        self.check_quarantine(&packet)?;
+        // Anyone can put an honest peer in `source`, only charge sources that signed
+        // the packet. Rate limit before validating the payload, that's the expensive
+        // work we want to bound.
+        self.verify(&packet.payload, &packet.source, &packet.sig)?;
+        self.check_rate_limit(&packet)?;
+
+        if let Err(err) = self.validate_payload(packet.source, &packet.payload) {
+            let source = packet.source;
+            let catch_up = self.catch_up(source, &err)?;
+            return if self.is_early(&packet, &err) && self.buffer_packet(packet) {
+                info!("[BRB] packet arrived early, buffering it: {:?}", err);
+                Ok(catch_up.into_iter().collect())
+            } else if let Some(catch_up_packet) = catch_up {
+                self.record_rejection(source, &err);
+                Ok(vec![catch_up_packet])
+            } else {
+                Err(err)
+            };
+        }
+
+        let mut packets_to_send = self.process_packet(packet)?;
+        packets_to_send.extend(self.process_buffered_packets()?);
+        Ok(packets_to_send)
+    }
+
+    /// Fails if the source of the packet is quarantined. Packets from ourselves are never dropped.
+    fn check_quarantine(
+        &self,
+        packet: &Packet<A, S, BRBDT::Op>,
+    ) -> Result<(), Error<A, S, BRBDT::ValidationError>> {
+        if packet.source != self.actor()
+            && self
+                .reputation
+                .is_quarantined(&packet.source, self.packets_handled)
+        {
+            Err(Error::Quarantined {
+                peer: packet.source,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Takes a token from the source's bucket for this kind of packet, failing if
+    /// the source has exceeded its rate limit. Packets from ourselves are never limited.
+    fn check_rate_limit(
+        &mut self,
+        packet: &Packet<A, S, BRBDT::Op>,
+    ) -> Result<(), Error<A, S, BRBDT::ValidationError>> {
+        let kind = match &packet.payload {
+            Payload::AntiEntropy { .. } => LimitedPayload::AntiEntropy,
+            Payload::BRB(Op::RequestValidation { .. }) => LimitedPayload::RequestValidation,
+            Payload::Membership(_) => LimitedPayload::Membership,
+            _ => return Ok(()),
+        };
+
+        if packet.source == self.actor()
+            || self
+                .rate_limiter
+                .allow(packet.source, kind, self.packets_handled)
+        {
+            Ok(())
+        } else {
+            Err(Error::RateLimited {
+                peer: packet.source,
+                kind,
+            })
+        }
+    }
+
+    /// Generates a Rejected packet to let the source of a packet that failed with err
+    /// know why we refused it, so that it can react without waiting on a timeout.
+    ///
+    /// Returns None if the packet was not at fault or was itself a rejection.
+    #[allow(clippy::type_complexity)]
+    pub fn reject(
+        &self,
+        packet: &Packet<A, S, BRBDT::Op>,
+        err: &Error<A, S, BRBDT::ValidationError>,
+    ) -> Result<Option<Packet<A, S, BRBDT::Op>>, Error<A, S, BRBDT::ValidationError>> {
+        let reason = match (&packet.payload, err.rejection_reason()) {
+            (Payload::Rejected { .. }, _) | (_, None) => return Ok(None),
+            (_, Some(reason)) => reason,
+        };
+        let dot = match &packet.payload {
+            Payload::BRB(op) => Some(op.msg().dot),
+            _ => None,
+        };
+
+        info!(
+            "[BRB] rejecting packet from {}: {:?}",
+            packet.source, reason
+        );
+        self.send(packet.source, Payload::Rejected { reason, dot })
+            .map(Some)
+    }
+
+    /// Generates an AntiEntropy request to peer if auto_catch_up is set, err shows
+    /// that we've fallen behind peer and we have not asked peer to help us catch up
+    /// within the last `catch_up_cooldown` packets.
+    #[allow(clippy::type_complexity)]
+    fn catch_up(
+        &mut self,
+        peer: A,
+        err: &Error<A, S, BRBDT::ValidationError>,
+    ) -> Result<Option<Packet<A, S, BRBDT::Op>>, Error<A, S, BRBDT::ValidationError>> {
+        if !self.auto_catch_up || !Self::is_behind(err) {
+            return Ok(None);
+        }
+
+        if let Some(last_catch_up) = self.last_catch_up.get(&peer) {
+            if self.packets_handled - last_catch_up < self.catch_up_cooldown {
+                info!(
+                    "[BRB] we are behind {} but recently asked to catch up",
+                    peer
+                );
+                return Ok(None);
+            }
+        }
+
+        info!(
+            "[BRB] we are behind {}, asking to catch up: {:?}",
+            peer, err
+        );
+        self.last_catch_up.insert(peer, self.packets_handled);
+        self.anti_entropy(peer).map(Some)
+    }
+
+    /// true if err shows that we are missing msgs or a generation the source has seen.
+    fn is_behind(err: &Error<A, S, BRBDT::ValidationError>) -> bool {
+        match err {
+            Error::Validation(err) => match err {
+                ValidationError::MsgDotNotTheNextDot {
+                    msg_dot,
+                    expected_dot,
+                }
+                | ValidationError::MsgDotNotNextDotToBeDelivered {
+                    msg_dot,
+                    expected_dot,
+                } => msg_dot.counter > expected_dot.counter,
+                // We signed the source's previous msg but never received its proof.
+                ValidationError::SourceAlreadyHasPendingMsg { .. } => true,
+                ValidationError::MessageFromDifferentGeneration { msg_gen, gen } => msg_gen > gen,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// true if the packet that failed validation with err arrived before we were ready for
+    /// it and may become valid once we've caught up, i.e. it's a valid proof for a future
+    /// dot or a validation request from a near-future generation.
+    ///
+    /// Proofs from a future generation are not buffered, we don't know the members of that
+    /// generation yet so we can't verify their signatures. We'll get them again when we
+    /// catch up.
+    fn is_early(
+        &self,
+        packet: &Packet<A, S, BRBDT::Op>,
+        err: &Error<A, S, BRBDT::ValidationError>,
+    ) -> bool {
+        match (&packet.payload, err) {
+            (
+                Payload::BRB(Op::ProofOfAgreement { .. }),
+                Error::Validation(ValidationError::MsgDotNotNextDotToBeDelivered {
+                    msg_dot,
+                    expected_dot,
+                }),
+            ) => msg_dot.counter > expected_dot.counter,
+            (
+                Payload::BRB(Op::RequestValidation { .. }),
+                Error::Validation(ValidationError::MessageFromDifferentGeneration { msg_gen, gen }),
+            ) => msg_gen > gen && msg_gen - gen <= self.generation_lookahead,
+            _ => false,
+        }
+    }
 
+    /// Adds an early packet to the reorder buffer of its source.
+    /// Returns false if the buffer for this source, or the buffer as a whole, is full.
+    fn buffer_packet(&mut self, packet: Packet<A, S, BRBDT::Op>) -> bool {
+        let total_buffered: usize = self.reorder_buffer.values().map(Vec::len).sum();
+        let buffered = self.reorder_buffer.entry(packet.source).or_default();
+        if buffered.contains(&packet) {
+            true
+        } else if buffered.len() < self.reorder_buffer_limit
+            && total_buffered < self.reorder_buffer_total_limit
+        {
+            buffered.push(packet);
+            true
+        } else {
+            if buffered.is_empty() {
+                self.reorder_buffer.remove(&packet.source);
+            }
+            false
+        }
+    }
+
+    /// true if our state has caught up to a buffered packet's payload.
+    fn is_ready(&self, payload: &Payload<A, S, BRBDT::Op>) -> bool {
+        match payload {
+            Payload::BRB(Op::ProofOfAgreement { msg, .. }) => {
+                msg.gen <= self.membership.gen
+                    && msg.dot.counter <= self.delivered.get(&msg.dot.actor) + 1
+            }
+            Payload::BRB(Op::RequestValidation { msg }) => msg.gen <= self.membership.gen,
+            _ => true,
+        }
+    }
+
+    /// Removes and returns a buffered packet that we are now ready to process, if any.
+    fn take_ready_buffered_packet(&mut self) -> Option<Packet<A, S, BRBDT::Op>> {
+        let (source, idx) = self.reorder_buffer.iter().find_map(|(source, packets)| {
+            packets
+                .iter()
+                .position(|p| self.is_ready(&p.payload))
+                .map(|idx| (*source, idx))
+        })?;
+
+        let packets = self.reorder_buffer.get_mut(&source)?;
+        let packet = packets.remove(idx);
+        if packets.is_empty() {
+            self.reorder_buffer.remove(&source);
+        }
+        Some(packet)
+    }
+
+    /// Processes buffered packets until none of the remaining ones are ready.
+    ///
+    /// Buffered packets were charged against their source's rate limit when they arrived.
+    /// They go through the same quarantine check and validation as packets that have just
+    /// arrived, and their source is penalized if they fail.
+    #[allow(clippy::type_complexity)]
+    fn process_buffered_packets(
+        &mut self,
+    ) -> Result<Vec<Packet<A, S, BRBDT::Op>>, Error<A, S, BRBDT::ValidationError>> {
+        let mut packets_to_send = Vec::new();
+        while let Some(packet) = self.take_ready_buffered_packet() {
+            let source = packet.source;
+
+            #[cfg(feature = "tracing")]
+            let _span = match &packet.payload {
+                Payload::BRB(op) => {
+                    let span = self.msg_span(packet.payload.kind(), op.msg());
+                    span.record("replayed", true);
+                    Some(span)
+                }
+                _ => None,
+            };
+
+            match self.process_buffered_packet(packet) {
+                Ok(packets) => packets_to_send.extend(packets),
+                Err(err) => {
+                    info!("[BRB] dropping buffered packet: {:?}", err);
+                    self.record_rejection(source, &err);
+                }
+            }
+        }
+        Ok(packets_to_send)
+    }
+
+    /// validates, then processes a packet taken from the reorder buffer.
+    #[allow(clippy::type_complexity)]
+    fn process_buffered_packet(
+        &mut self,
+        packet: Packet<A, S, BRBDT::Op>,
+    ) -> Result<Vec<Packet<A, S, BRBDT::Op>>, Error<A, S, BRBDT::ValidationError>> {
+        self.check_quarantine(&packet)?;
         self.validate_packet(&packet)?;
         self.process_packet(packet)
     }
@@ -373,8 +881,10 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
                     packets_to_send.extend(
                         // TODO: This can be optimized using Vec::binary_search. This is linear in the number of messages.
                         msgs.iter()
-                            .filter(|(msg, _proof)| msg.dot.counter > seen_counter)
-                            .map(|(msg, proof)| {
+                            .filter(|(msg, _proof, _outcome)| msg.dot.counter > seen_counter)
+                            .map(|(msg, proof, _outcome)| {
+                                #[cfg(feature = "tracing")]
+                                let _span = self.msg_span("catch_up", msg);
                                 self.send(
                                     source,
                                     Payload::BRB(Op::ProofOfAgreement {
@@ -390,15 +900,56 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
                 Ok(packets_to_send)
             }
             Payload::BRB(op) => self.process_brb_op(packet.source, op),
-            Payload::Membership(boxed_vote) => self
-                .membership
-                .handle_vote(*boxed_vote)
-                .map_err(Error::Membership)?
-                .into_iter()
-                .map(|vote_msg| {
-                    self.send(vote_msg.dest, Payload::Membership(Box::new(vote_msg.vote)))
-                })
-                .collect(),
+            Payload::Rejected { reason, dot } => {
+                info!(
+                    "[BRB] {} rejected our packet (dot: {:?}): {:?}",
+                    source, dot, reason
+                );
+                match reason {
+                    // We are the ones lagging behind, ask the peer to bring us up to date.
+                    RejectionReason::WrongGeneration { gen } if gen > self.membership.gen => {
+                        Ok(vec![self.anti_entropy(source)?])
+                    }
+                    RejectionReason::Behind => Ok(vec![self.anti_entropy(source)?]),
+                    _ => Ok(vec![]),
+                }
+            }
+            Payload::Membership(boxed_vote) => {
+                let vote_msgs = self
+                    .membership
+                    .handle_vote(*boxed_vote)
+                    .map_err(Error::from)?;
+                self.prune_signed_validations();
+                #[cfg(feature = "metrics")]
+                self.abandon_past_generation_execs();
+                vote_msgs
+                    .into_iter()
+                    .map(|vote_msg| {
+                        self.send(vote_msg.dest, Payload::Membership(Box::new(vote_msg.vote)))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Forgets the signatures we gave for msgs from a past generation.
+    ///
+    /// We only keep them to answer resent requests for the same msg, and we refuse to
+    /// sign msgs from a past generation. Without this, the signature for a msg that never
+    /// gathers a proof would be kept forever.
+    fn prune_signed_validations(&mut self) {
+        let gen = self.membership.gen;
+        self.signed_validations
+            .retain(|_, (signed_msg, _)| signed_msg.gen >= gen);
+    }
+
+    /// Stops timing the msgs we initiated in a past generation that never gathered a proof.
+    /// Members refuse to sign msgs from a past generation, so these will never be committed.
+    #[cfg(feature = "metrics")]
+    fn abandon_past_generation_execs(&mut self) {
+        let gen = self.membership.gen;
+        for msg in self.pending_proof.keys().filter(|msg| msg.gen < gen) {
+            self.metrics.record_abandoned(&msg.dot);
         }
     }
 
@@ -414,14 +965,32 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
                 info!("[BRB] request for validation");
                 self.received.apply(msg.dot);
 
-                // NOTE: we do not need to store this message, it will be sent back to us
-                // with the proof of agreement. Our signature will prevent tampering.
-                let sig = self.sign(&msg)?;
+                // NOTE: the msg will be sent back to us with the proof of agreement and our
+                // signature will prevent tampering. We only hold on to it until it's delivered
+                // so that we can answer a resent request with the same signature.
+                let sig = match self.signed_validations.get(&source) {
+                    Some((signed_msg, sig)) if signed_msg == &msg => {
+                        info!("[BRB] re-sending signature for duplicate request");
+                        sig.clone()
+                    }
+                    _ => {
+                        let sig = self.sign(&msg)?;
+                        self.signed_validations
+                            .insert(source, (msg.clone(), sig.clone()));
+                        sig
+                    }
+                };
                 let validation = Op::SignedValidated { msg, sig };
                 Ok(vec![self.send(source, Payload::BRB(validation))?])
             }
             Op::SignedValidated { msg, sig } => {
                 info!("[BRB] signed validated");
+                if self.is_delivered(&msg) {
+                    // A signature arriving after we delivered our own msg must not start a
+                    // new proof, members yet to receive the first would deliver another one.
+                    info!("[BRB] msg was already delivered, ignoring late signature");
+                    return Ok(vec![]);
+                }
                 self.pending_proof
                     .entry(msg.clone())
                     .or_default()
@@ -457,6 +1026,39 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
             }
             Op::ProofOfAgreement { msg, proof } => {
                 info!("[BRB] proof of agreement: {:?}", msg);
+                if self.is_delivered(&msg) {
+                    // The initiator only resends proofs it is still waiting on, so our Delivered
+                    // ack must have been lost. Proofs relayed by anyone else, e.g. in answer to
+                    // an anti-entropy request, are not a sign that the initiator is waiting.
+                    if source != msg.dot.actor {
+                        info!("[BRB] msg was already delivered, ignoring relayed proof");
+                        return Ok(vec![]);
+                    }
+                    info!("[BRB] msg was already delivered, re-acknowledging delivery");
+                    return Ok(vec![
+                        self.send(msg.dot.actor, Payload::BRB(Op::Delivered { msg }))?
+                    ]);
+                }
+
+                // Apply the op, the data type gets a final say on whether it takes effect.
+                // This is done before we update any state so that the data type sees what
+                // was delivered before this msg, and a failure leaves our state untouched.
+                let members = self.membership.members(msg.gen)?;
+                let ctx = ValidationContext {
+                    source: &msg.dot.actor,
+                    dot: &msg.dot,
+                    gen: msg.gen,
+                    members: &members,
+                    delivered: &self.delivered,
+                };
+                let outcome = match self.dt.try_apply(&ctx, msg.op.clone()) {
+                    Ok(()) => DeliveryOutcome::Applied,
+                    Err(err) => {
+                        info!("[BRB] delivered op was rejected by data type: {:?}", err);
+                        DeliveryOutcome::RejectedByDataType
+                    }
+                };
+
                 // We may not have been in the subset of members to validate this clock
                 // so we may not have had the chance to increment received. We must bring
                 // received up to this msg's timestamp.
@@ -466,19 +1068,23 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
                 self.received.apply(msg.dot);
                 self.delivered.apply(msg.dot);
 
-                // Log this op in our history with proof
-                self.history_from_source
-                    .entry(msg.dot.actor)
-                    .or_default()
-                    .push((msg.clone(), proof));
-
                 // Remove the message from pending_proof since we have a proof of agreement
                 // NOTE: this is a no-op for most members, only the initiating member will have
                 //       the message in it's pending_proof set.
                 self.pending_proof.remove(&msg);
 
-                // Apply the op
-                self.dt.apply(msg.op.clone());
+                // We no longer need to remember our signature for this msg.
+                if let Some((signed_msg, _)) = self.signed_validations.get(&msg.dot.actor) {
+                    if signed_msg.dot.counter <= msg.dot.counter {
+                        self.signed_validations.remove(&msg.dot.actor);
+                    }
+                }
+
+                // Log this op in our history with proof
+                self.history_from_source
+                    .entry(msg.dot.actor)
+                    .or_default()
+                    .push((msg.clone(), proof, outcome));
 
                 Ok(vec![self.send(
                     msg.dot.actor,
@@ -498,6 +1104,8 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
                     // We've seen a super-majority of delivery confirmations so we can
                     // be confident this operation has been committed.
                     self.pending_delivery.remove(&msg);
+                    #[cfg(feature = "metrics")]
+                    self.metrics.record_commit(&msg.dot);
                 }
                 Ok(vec![])
             }
@@ -523,6 +1131,7 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
             Payload::AntiEntropy { .. } => Ok(()),
             Payload::BRB(op) => self.validate_brb_op(from, op),
             Payload::Membership(_) => Ok(()), // membership votes are validated inside membership.handle_vote(..)
+            Payload::Rejected { .. } => Ok(()),
         }
     }
 
@@ -534,8 +1143,23 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
     ) -> Result<(), Error<A, S, BRBDT::ValidationError>> {
         match op {
             Op::RequestValidation { msg } => {
+                let already_signed = self
+                    .signed_validations
+                    .get(&from)
+                    .filter(|(signed_msg, _)| signed_msg.dot == msg.dot);
+
                 if from != msg.dot.actor {
                     Err(ValidationError::PacketSourceIsNotDot { from, dot: msg.dot })
+                } else if let Some((signed_msg, _)) = already_signed {
+                    if signed_msg == msg {
+                        // We've already validated and signed this exact msg, it's safe to
+                        // hand back the same signature.
+                        Ok(())
+                    } else {
+                        Err(ValidationError::SourceSentDifferentMsgForSignedDot {
+                            msg_dot: msg.dot,
+                        })
+                    }
                 } else if msg.dot != self.received.inc(from) {
                     Err(ValidationError::MsgDotNotTheNextDot {
                         msg_dot: msg.dot,
@@ -561,56 +1185,106 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
                         members: self.membership.members(self.membership.gen)?,
                     })
                 } else {
+                    let ctx = ValidationContext {
+                        source: &from,
+                        dot: &msg.dot,
+                        gen: msg.gen,
+                        members: &self.membership.members(self.membership.gen)?,
+                        delivered: &self.delivered,
+                    };
                     self.dt
-                        .validate(&from, &msg.op)
+                        .validate_with_context(&ctx, &msg.op)
                         .map_err(ValidationError::DataTypeFailedValidation)
                 }
             }
             Op::SignedValidated { msg, sig } => {
-                self.verify(&msg, &from, sig)?;
+                self.verify(msg, &from, sig)?;
 
                 if self.actor() != msg.dot.actor {
-                    Err(ValidationError::SignedValidatedForPacketWeDidNotRequest)
+                    Err(ValidationError::SignedValidatedForPacketWeDidNotRequest { from })
                 } else {
                     Ok(())
                 }
             }
             Op::ProofOfAgreement { msg, proof } => {
-                let msg_members = self.membership.members(msg.gen)?;
-                if self.delivered.inc(msg.dot.actor) != msg.dot {
-                    Err(ValidationError::MsgDotNotNextDotToBeDelivered {
-                        msg_dot: msg.dot,
-                        expected_dot: self.delivered.inc(msg.dot.actor),
+                if self.is_delivered(msg) {
+                    // We've already verified a proof for this exact msg, we only need to
+                    // re-acknowledge it.
+                    Ok(())
+                } else if msg.gen > self.membership.gen {
+                    Err(ValidationError::MessageFromDifferentGeneration {
+                        msg_gen: msg.gen,
+                        gen: self.membership.gen,
                     })
-                } else if !self.supermajority(proof.len(), msg.gen)? {
-                    Err(ValidationError::NotEnoughSignaturesToFormQuorum)
-                } else if !proof
-                    .iter()
-                    .all(|(signer, _)| msg_members.contains(&signer))
-                {
-                    Err(ValidationError::ProofContainsSignaturesFromNonMembers)
-                } else if proof
-                    .iter()
-                    .map(|(signer, sig)| self.verify(&msg, &signer, &sig))
-                    .collect::<Result<Vec<()>, _>>()
-                    .is_err()
-                {
-                    Err(ValidationError::ProofContainsInvalidSignatures)
                 } else {
-                    Ok(())
+                    // The proof is checked before the dot so that a valid proof that
+                    // arrived early can be told apart from garbage.
+                    self.validate_proof(from, msg, proof)?;
+
+                    if self.delivered.inc(msg.dot.actor) != msg.dot {
+                        Err(ValidationError::MsgDotNotNextDotToBeDelivered {
+                            msg_dot: msg.dot,
+                            expected_dot: self.delivered.inc(msg.dot.actor),
+                        })
+                    } else {
+                        Ok(())
+                    }
                 }
             }
             Op::Delivered { msg } => {
                 if msg.dot.actor != self.actor() {
-                    Err(ValidationError::DeliveredForPacketWeDidNotInitiate)
-                } else if !self.pending_delivery.contains_key(msg) {
-                    Err(ValidationError::DeliveredForPacketWeAreNotWaitingOn)
+                    Err(ValidationError::DeliveredForPacketWeDidNotInitiate { from })
+                } else if !self.pending_delivery.contains_key(msg) && !self.is_delivered(msg) {
+                    // A late ack for a msg we've already committed is harmless, e.g. a peer
+                    // re-acknowledges our proof after we sent it in answer to anti-entropy.
+                    Err(ValidationError::DeliveredForPacketWeAreNotWaitingOn { from })
                 } else {
                     Ok(())
                 }
             }
         }
-        .map_err(Error::Validation)
+        .map_err(Error::from)
+    }
+
+    /// Validates that a proof sent to us by `from` holds a supermajority of valid
+    /// signatures over msg from the members of msg's generation.
+    fn validate_proof(
+        &self,
+        from: A,
+        msg: &Msg<A, BRBDT::Op>,
+        proof: &BTreeMap<A, S>,
+    ) -> Result<(), Error<A, S, BRBDT::ValidationError>> {
+        let msg_members = self.membership.members(msg.gen)?;
+        let result = if !self.supermajority(proof.len(), msg.gen)? {
+            Err(ValidationError::NotEnoughSignaturesToFormQuorum { from })
+        } else if !proof.iter().all(|(signer, _)| msg_members.contains(signer)) {
+            Err(ValidationError::ProofContainsSignaturesFromNonMembers { from })
+        } else if proof
+            .iter()
+            .map(|(signer, sig)| self.verify(msg, signer, sig))
+            .collect::<Result<Vec<()>, _>>()
+            .is_err()
+        {
+            Err(ValidationError::ProofContainsInvalidSignatures { from })
+        } else {
+            Ok(())
+        };
+        result.map_err(Error::from)
+    }
+
+    /// true if msg is in our history, i.e. we've already delivered it to the data type.
+    fn is_delivered(&self, msg: &Msg<A, BRBDT::Op>) -> bool {
+        // history from a source is ordered by dot, starting with counter 1.
+        msg.dot
+            .counter
+            .checked_sub(1)
+            .and_then(|idx| {
+                self.history_from_source
+                    .get(&msg.dot.actor)?
+                    .get(idx as usize)
+            })
+            .map(|(delivered_msg, _proof, _outcome)| delivered_msg == msg)
+            .unwrap_or(false)
     }
 
     /// true if n represents a supermajority of votes for a given generation.
@@ -626,7 +1300,7 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
     /// for each actor in targets and returns a list of all the generated
     /// packets, ready to be sent by transport layer.
     #[allow(clippy::type_complexity)]
-    fn broadcast(
+    pub(crate) fn broadcast(
         &self,
         payload: &Payload<A, S, BRBDT::Op>,
         targets: BTreeSet<A>,
@@ -641,7 +1315,7 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
 
     /// Generates a packet from self to dest containing payload plus our payload signature.
     #[allow(clippy::type_complexity)]
-    fn send(
+    pub(crate) fn send(
         &self,
         dest: A,
         payload: Payload<A, S, BRBDT::Op>,
@@ -656,7 +1330,10 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
     }
 
     /// Signs data with our key
-    fn sign(&self, data: impl Serialize) -> Result<S, Error<A, S, BRBDT::ValidationError>> {
+    pub(crate) fn sign(
+        &self,
+        data: impl Serialize,
+    ) -> Result<S, Error<A, S, BRBDT::ValidationError>> {
         let bytes = bincode::serialize(&data)?;
         Ok(self.membership.id.sign(&bytes))
     }
@@ -669,7 +1346,9 @@ impl<A: Actor<S>, SA: SigningActor<A, S>, S: Sig, BRBDT: BRBDataType<A>>
         sig: &S,
     ) -> Result<(), Error<A, S, BRBDT::ValidationError>> {
         let bytes = bincode::serialize(&data)?;
-        signer.verify(&bytes, &sig)?;
+        #[cfg(feature = "metrics")]
+        self.metrics.record_signature_verified();
+        signer.verify(&bytes, sig)?;
         Ok(())
     }
 }
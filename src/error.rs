@@ -11,6 +11,8 @@
 
 use std::collections::BTreeSet;
 
+use crate::packet::RejectionReason;
+use crate::rate_limit::LimitedPayload;
 use brb_membership::signature;
 use brb_membership::{Actor, Generation, Sig};
 use crdts::Dot;
@@ -37,6 +39,36 @@ pub enum Error<A: Actor<S> + 'static, S: Sig + 'static, V: fmt::Debug + error::E
     /// Failure when working with signature
     #[error("Failure when working with signature")]
     Signature(#[from] signature::Error),
+
+    /// Packet was dropped because its source exceeded its rate limit
+    #[error("Packet was dropped because {peer} exceeded its rate limit for {kind:?} packets")]
+    RateLimited {
+        /// the peer that sent the packet
+        peer: A,
+        /// the kind of packet that was limited
+        kind: LimitedPayload,
+    },
+
+    /// Packet was dropped because its source is quarantined due to bad reputation
+    #[error("Packet was dropped because {peer} is quarantined")]
+    Quarantined {
+        /// the peer that sent the packet
+        peer: A,
+    },
+}
+
+/// Classifies errors by whose fault they are, so that callers can decide between
+/// retrying, catching up and reporting the peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Our own fault, e.g. we failed to serialize. The packet is not to blame.
+    Local,
+    /// The packet arrived at the wrong time, e.g. the source is slightly ahead of or
+    /// behind us. Retrying or catching up should resolve it.
+    Transient,
+    /// The source misbehaved in a way an honest member never would, e.g. it forged
+    /// a proof or asked us to sign conflicting msgs.
+    Byzantine,
 }
 
 /// Enumerates types of packet validation errors.
@@ -68,6 +100,14 @@ pub enum ValidationError<
         expected_dot: Dot<A>,
     },
 
+    /// We already signed a different message for this dot, the source is trying to
+    /// get us to sign two conflicting messages.
+    #[error("We already signed a different message for dot {msg_dot:?}")]
+    SourceSentDifferentMsgForSignedDot {
+        /// dot of the message
+        msg_dot: Dot<A>,
+    },
+
     /// The source of this message already has a pending message, we can not start a new operation until the first one has completed
     #[error("The source of this message already has a pending message, we can not start a new operation until the first one has completed")]
     SourceAlreadyHasPendingMsg {
@@ -104,8 +144,13 @@ pub enum ValidationError<
     InvalidSignature,
 
     /// We received a SignedValidated packet for a message we did not request
-    #[error("We received a SignedValidated packet for a message we did not request")]
-    SignedValidatedForPacketWeDidNotRequest,
+    #[error(
+        "We received a SignedValidated packet for a message we did not request (from: {from})"
+    )]
+    SignedValidatedForPacketWeDidNotRequest {
+        /// actor who sent the packet
+        from: A,
+    },
 
     /// Message dot to be applied is not the next message to be delivered
     #[error("Message dot {msg_dot:?} to be applied is not the next message to be delivered (expected: {expected_dot:?}")]
@@ -117,29 +162,229 @@ pub enum ValidationError<
     },
 
     /// The proof did not contain enough signatures to form quorum
-    #[error("The proof did not contain enough signatures to form quorum")]
-    NotEnoughSignaturesToFormQuorum,
+    #[error("The proof did not contain enough signatures to form quorum (from: {from})")]
+    NotEnoughSignaturesToFormQuorum {
+        /// actor who sent the packet
+        from: A,
+    },
 
     /// Proof contains signatures from non-members
-    #[error("Proof contains signatures from non-members")]
-    ProofContainsSignaturesFromNonMembers,
+    #[error("Proof contains signatures from non-members (from: {from})")]
+    ProofContainsSignaturesFromNonMembers {
+        /// actor who sent the packet
+        from: A,
+    },
 
     /// Proof contains invalid signatures
-    #[error("Proof contains invalid signatures")]
-    ProofContainsInvalidSignatures,
+    #[error("Proof contains invalid signatures (from: {from})")]
+    ProofContainsInvalidSignatures {
+        /// actor who sent the packet
+        from: A,
+    },
 
     /// We received a Op::Delivered packet for a message we did not initiate. Only the initiator should
     /// receive these delivered packets.
-    #[error("We did not initiate this msg so we shouldn't be notified that it was delivered")]
-    DeliveredForPacketWeDidNotInitiate,
+    #[error("We did not initiate this msg so we shouldn't be notified that it was delivered (from: {from})")]
+    DeliveredForPacketWeDidNotInitiate {
+        /// actor who sent the packet
+        from: A,
+    },
 
-    /// We received an Op::Delivered packet for a message we are no longer waiting on.
-    /// This can happen when we've already received a super-majority of Delivered packets and have
-    /// cleared our local buffer.
-    #[error("We are no longer waiting for delivery notifications for this packet")]
-    DeliveredForPacketWeAreNotWaitingOn,
+    /// We received an Op::Delivered packet for a message we are not waiting on and have not
+    /// delivered. Late acks for messages we've already committed are ignored.
+    #[error("We are no longer waiting for delivery notifications for this packet (from: {from})")]
+    DeliveredForPacketWeAreNotWaitingOn {
+        /// actor who sent the packet
+        from: A,
+    },
 
     /// Phantom, unused.
     #[error("This variant is only here to satisfy the type checker (we need to use S in a field)")]
     PhantomSig(core::marker::PhantomData<S>),
 }
+
+impl<A: Actor<S>, S: Sig, V: fmt::Debug + error::Error> Error<A, S, V> {
+    /// How serious this error is and whose fault it is.
+    pub fn severity(&self) -> Severity {
+        match self {
+            Error::Membership(err) => membership_severity(err),
+            Error::Encoding(_) => Severity::Local,
+            Error::Validation(err) => err.severity(),
+            Error::Signature(_) => Severity::Byzantine,
+            Error::RateLimited { .. } => Severity::Transient,
+            Error::Quarantined { .. } => Severity::Byzantine,
+        }
+    }
+
+    /// true if the source of the packet misbehaved.
+    pub fn is_byzantine(&self) -> bool {
+        self.severity() == Severity::Byzantine
+    }
+
+    /// true if the packet may succeed if it's retried later or once we've caught up.
+    pub fn is_retryable(&self) -> bool {
+        self.severity() == Severity::Transient
+    }
+
+    /// The actor at fault for this error, where known.
+    ///
+    /// Note that the source of a packet with an invalid signature is not known, the
+    /// packet may have been forged by someone else.
+    pub fn offender(&self) -> Option<&A> {
+        match self {
+            Error::Validation(err) => err.offender(),
+            Error::Membership(err) => membership_offender(err),
+            Error::RateLimited { peer, .. } | Error::Quarantined { peer } => Some(peer),
+            _ => None,
+        }
+    }
+
+    /// The reason to give the source of a packet that failed with this error.
+    ///
+    /// Returns None if the packet is not at fault, e.g. we failed to serialize.
+    pub fn rejection_reason(&self) -> Option<RejectionReason> {
+        match self {
+            Error::Membership(_) => Some(RejectionReason::MembershipRejected),
+            Error::Encoding(_) => None,
+            Error::Validation(err) => Some(err.rejection_reason()),
+            Error::Signature(_) => Some(RejectionReason::InvalidSignature),
+            Error::RateLimited { .. } => Some(RejectionReason::RateLimited),
+            Error::Quarantined { .. } => Some(RejectionReason::Quarantined),
+        }
+    }
+}
+
+/// How serious a membership error is and whose fault it is.
+fn membership_severity<A: Actor<S>, S: Sig>(err: &brb_membership::Error<A, S>) -> Severity {
+    use brb_membership::Error as Membership;
+    match err {
+        Membership::IO(_) | Membership::Encoding(_) | Membership::NoMembers => Severity::Local,
+        Membership::InvalidSignature(_)
+        | Membership::VoterChangedMind { .. }
+        | Membership::ExistingVoteIncompatibleWithNewVote { .. }
+        | Membership::InvalidVoteInHistory(_)
+        | Membership::SuperMajorityBallotIsNotSuperMajority { .. } => Severity::Byzantine,
+        // The remaining errors are most likely due to our membership views having diverged.
+        Membership::VoteNotForNextGeneration { .. }
+        | Membership::WrongDestination { .. }
+        | Membership::MembersAtCapacity { .. }
+        | Membership::JoinRequestForExistingMember { .. }
+        | Membership::LeaveRequestForNonMember { .. }
+        | Membership::VoteFromNonMember { .. }
+        | Membership::InvalidGeneration(_) => Severity::Transient,
+    }
+}
+
+/// The voter at fault for a membership error, where known.
+///
+/// Votes are signed by their voter, so a voter whose signed votes conflict is at fault
+/// whoever relayed them. For other errors the relaying source is to blame.
+fn membership_offender<A: Actor<S>, S: Sig>(err: &brb_membership::Error<A, S>) -> Option<&A> {
+    use brb_membership::Error as Membership;
+    match err {
+        Membership::VoterChangedMind { reconfigs } => {
+            // Every voter that is listed more than once changed their mind.
+            reconfigs
+                .iter()
+                .map(|(voter, _)| voter)
+                .find(|voter| reconfigs.iter().filter(|(v, _)| v == *voter).count() > 1)
+        }
+        Membership::ExistingVoteIncompatibleWithNewVote { existing_vote } => {
+            Some(&existing_vote.voter)
+        }
+        _ => None,
+    }
+}
+
+impl<A: Actor<S>, S: Sig, V: fmt::Debug + error::Error> ValidationError<A, S, V> {
+    /// How serious this error is and whose fault it is.
+    pub fn severity(&self) -> Severity {
+        match self {
+            ValidationError::MsgDotNotTheNextDot { .. }
+            | ValidationError::SourceAlreadyHasPendingMsg { .. }
+            | ValidationError::MessageFromDifferentGeneration { .. }
+            | ValidationError::SourceIsNotVotingMember { .. }
+            | ValidationError::MsgDotNotNextDotToBeDelivered { .. }
+            | ValidationError::DeliveredForPacketWeAreNotWaitingOn { .. } => Severity::Transient,
+            // Data types validate ops against our local state, an honest source may have
+            // seen ops that we have not, e.g. the deposit that funds a transfer.
+            ValidationError::DataTypeFailedValidation(_) => Severity::Transient,
+            ValidationError::PacketSourceIsNotDot { .. }
+            | ValidationError::SourceSentDifferentMsgForSignedDot { .. }
+            | ValidationError::InvalidSignature
+            | ValidationError::SignedValidatedForPacketWeDidNotRequest { .. }
+            | ValidationError::NotEnoughSignaturesToFormQuorum { .. }
+            | ValidationError::ProofContainsSignaturesFromNonMembers { .. }
+            | ValidationError::ProofContainsInvalidSignatures { .. }
+            | ValidationError::DeliveredForPacketWeDidNotInitiate { .. } => Severity::Byzantine,
+            ValidationError::PhantomSig(_) => Severity::Local,
+        }
+    }
+
+    /// true if the source of the packet misbehaved.
+    pub fn is_byzantine(&self) -> bool {
+        self.severity() == Severity::Byzantine
+    }
+
+    /// true if the packet may succeed if it's retried later or once we've caught up.
+    pub fn is_retryable(&self) -> bool {
+        self.severity() == Severity::Transient
+    }
+
+    /// The actor at fault for this error, where known.
+    pub fn offender(&self) -> Option<&A> {
+        match self {
+            ValidationError::PacketSourceIsNotDot { from, .. }
+            | ValidationError::SourceIsNotVotingMember { from, .. }
+            | ValidationError::SignedValidatedForPacketWeDidNotRequest { from }
+            | ValidationError::NotEnoughSignaturesToFormQuorum { from }
+            | ValidationError::ProofContainsSignaturesFromNonMembers { from }
+            | ValidationError::ProofContainsInvalidSignatures { from }
+            | ValidationError::DeliveredForPacketWeDidNotInitiate { from }
+            | ValidationError::DeliveredForPacketWeAreNotWaitingOn { from } => Some(from),
+            // the source of the packet must be the actor of the msg dot
+            ValidationError::SourceSentDifferentMsgForSignedDot { msg_dot } => Some(&msg_dot.actor),
+            _ => None,
+        }
+    }
+
+    /// The reason to give the source of a packet that failed with this error.
+    pub fn rejection_reason(&self) -> RejectionReason {
+        match self {
+            ValidationError::PacketSourceIsNotDot { .. } => RejectionReason::Malformed,
+            ValidationError::MsgDotNotTheNextDot {
+                msg_dot,
+                expected_dot,
+            }
+            | ValidationError::MsgDotNotNextDotToBeDelivered {
+                msg_dot,
+                expected_dot,
+            } => {
+                if msg_dot.counter < expected_dot.counter {
+                    RejectionReason::Behind
+                } else {
+                    RejectionReason::Ahead
+                }
+            }
+            ValidationError::SourceSentDifferentMsgForSignedDot { .. } => RejectionReason::Conflict,
+            ValidationError::SourceAlreadyHasPendingMsg { .. } => RejectionReason::PendingMsg,
+            ValidationError::MessageFromDifferentGeneration { gen, .. } => {
+                RejectionReason::WrongGeneration { gen: *gen }
+            }
+            ValidationError::SourceIsNotVotingMember { .. } => RejectionReason::NotAMember,
+            ValidationError::DataTypeFailedValidation(_) => RejectionReason::DataTypeRejected,
+            ValidationError::InvalidSignature => RejectionReason::InvalidSignature,
+            ValidationError::NotEnoughSignaturesToFormQuorum { .. }
+            | ValidationError::ProofContainsSignaturesFromNonMembers { .. }
+            | ValidationError::ProofContainsInvalidSignatures { .. } => {
+                RejectionReason::InvalidProof
+            }
+            ValidationError::SignedValidatedForPacketWeDidNotRequest { .. }
+            | ValidationError::DeliveredForPacketWeDidNotInitiate { .. }
+            | ValidationError::DeliveredForPacketWeAreNotWaitingOn { .. } => {
+                RejectionReason::NotExpected
+            }
+            ValidationError::PhantomSig(_) => RejectionReason::Malformed,
+        }
+    }
+}
@@ -0,0 +1,259 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! A BFT replicated key-value store.
+//!
+//! The actor who first writes a key owns it, only the owner may overwrite or delete it.
+//! Once a key is deleted it is free to be claimed again.
+//!
+//! Each write carries a version one greater than the version of the entry it replaces.
+//! Replicas resolve concurrent writes, i.e. two actors claiming the same free key, by
+//! keeping the write with the highest (version, actor), so all replicas converge
+//! regardless of the order in which they deliver ops.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::brb_data_type::BRBDataType;
+
+/// An operation on the key-value store.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KvOp<A, K, V> {
+    /// Write a value to a key.
+    Put {
+        /// the key to write
+        key: K,
+        /// the value to write
+        value: V,
+        /// the actor performing the write, must be the source of the op
+        actor: A,
+        /// version of the entry after this write
+        version: u64,
+    },
+    /// Remove a key.
+    Delete {
+        /// the key to remove
+        key: K,
+        /// the actor performing the delete, must be the source of the op
+        actor: A,
+        /// version of the entry after this delete
+        version: u64,
+    },
+}
+
+impl<A, K, V> KvOp<A, K, V> {
+    /// the key this op writes to.
+    pub fn key(&self) -> &K {
+        match self {
+            KvOp::Put { key, .. } | KvOp::Delete { key, .. } => key,
+        }
+    }
+
+    /// the actor performing this op.
+    pub fn actor(&self) -> &A {
+        match self {
+            KvOp::Put { actor, .. } | KvOp::Delete { actor, .. } => actor,
+        }
+    }
+
+    /// version of the entry after this op.
+    pub fn version(&self) -> u64 {
+        match self {
+            KvOp::Put { version, .. } | KvOp::Delete { version, .. } => *version,
+        }
+    }
+}
+
+/// The reasons the key-value store refuses an op.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum KvError {
+    /// The actor named in the op is not the source of the op.
+    #[error("op actor is not the source of the op")]
+    ActorIsNotSource,
+    /// The key is owned by another actor.
+    #[error("key is owned by another actor")]
+    NotTheOwner,
+    /// The key does not exist.
+    #[error("key does not exist")]
+    NoSuchKey,
+    /// The op does not follow the current version of the entry.
+    #[error("op version {version} does not follow the current version {current}")]
+    WrongVersion {
+        /// version of the op
+        version: u64,
+        /// version of the entry in our store
+        current: u64,
+    },
+}
+
+/// A versioned entry of the store, a deleted key is kept as a tombstone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry<A, V> {
+    /// the value, None if the key has been deleted
+    pub value: Option<V>,
+    /// the actor who last wrote to this entry
+    pub owner: A,
+    /// the number of writes to this key
+    pub version: u64,
+}
+
+/// A key-value store where keys are owned by the actor that wrote them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BRBKvStore<A, K, V> {
+    /// the actor of this replica, for generating ops
+    pub actor: A,
+    entries: BTreeMap<K, Entry<A, V>>,
+}
+
+impl<A, K, V> BRBKvStore<A, K, V>
+where
+    A: Ord + Clone,
+    K: Ord,
+{
+    /// read the value of a key.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).and_then(|e| e.value.as_ref())
+    }
+
+    /// the actor owning a key, None if the key does not exist.
+    pub fn owner(&self, key: &K) -> Option<&A> {
+        self.entries
+            .get(key)
+            .filter(|e| e.value.is_some())
+            .map(|e| &e.owner)
+    }
+
+    /// true if the key exists.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// iterate over the keys and values in the store, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries
+            .iter()
+            .filter_map(|(k, e)| e.value.as_ref().map(|v| (k, v)))
+    }
+
+    /// the number of keys in the store.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// true if the store has no keys.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the entry of a key, including tombstones of deleted keys.
+    pub fn entry(&self, key: &K) -> Option<&Entry<A, V>> {
+        self.entries.get(key)
+    }
+
+    /// build an op writing a value to a key as this replica's actor.
+    pub fn put(&self, key: K, value: V) -> KvOp<A, K, V> {
+        let version = self.next_version(&key);
+        KvOp::Put {
+            key,
+            value,
+            actor: self.actor.clone(),
+            version,
+        }
+    }
+
+    /// build an op deleting a key as this replica's actor.
+    pub fn delete(&self, key: K) -> KvOp<A, K, V> {
+        let version = self.next_version(&key);
+        KvOp::Delete {
+            key,
+            actor: self.actor.clone(),
+            version,
+        }
+    }
+
+    fn next_version(&self, key: &K) -> u64 {
+        self.entries.get(key).map(|e| e.version).unwrap_or(0) + 1
+    }
+}
+
+impl<A, K, V> BRBDataType<A> for BRBKvStore<A, K, V>
+where
+    A: Ord + Clone + Debug + Hash + Serialize,
+    K: Ord + Clone + Debug + Hash + Serialize,
+    V: Clone + Debug + Hash + Eq + Serialize,
+{
+    type Op = KvOp<A, K, V>;
+    type ValidationError = KvError;
+
+    fn new(actor: A) -> Self {
+        BRBKvStore {
+            actor,
+            entries: Default::default(),
+        }
+    }
+
+    fn validate(&self, source: &A, op: &Self::Op) -> Result<(), Self::ValidationError> {
+        if op.actor() != source {
+            return Err(KvError::ActorIsNotSource);
+        }
+
+        let entry = self.entries.get(op.key());
+        let live_owner = entry.filter(|e| e.value.is_some()).map(|e| &e.owner);
+        match (op, live_owner) {
+            (KvOp::Delete { .. }, None) => return Err(KvError::NoSuchKey),
+            (_, Some(owner)) if owner != source => return Err(KvError::NotTheOwner),
+            _ => (),
+        }
+
+        let current = entry.map(|e| e.version).unwrap_or(0);
+        if op.version() != current + 1 {
+            Err(KvError::WrongVersion {
+                version: op.version(),
+                current,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn apply(&mut self, op: Self::Op) {
+        let (key, value, actor, version) = match op {
+            KvOp::Put {
+                key,
+                value,
+                actor,
+                version,
+            } => (key, Some(value), actor, version),
+            KvOp::Delete {
+                key,
+                actor,
+                version,
+            } => (key, None, actor, version),
+        };
+
+        // last writer wins, ties between concurrent writes are broken by actor.
+        let wins = self
+            .entries
+            .get(&key)
+            .map(|e| (version, &actor) > (e.version, &e.owner))
+            .unwrap_or(true);
+        if wins {
+            let entry = Entry {
+                value,
+                owner: actor,
+                version,
+            };
+            self.entries.insert(key, entry);
+        }
+    }
+}
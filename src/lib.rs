@@ -16,15 +16,36 @@ pub use brb_membership as membership;
 pub use brb_membership::{Actor, Error as MembershipError, Sig, SigningActor};
 
 pub mod deterministic_brb;
-pub use deterministic_brb::DeterministicBRB;
+pub use deterministic_brb::{DeliveryOutcome, DeterministicBRB};
 
 pub mod error;
-pub use error::{Error, ValidationError};
+pub use error::{Error, Severity, ValidationError};
 
 pub mod net;
 
 pub mod packet;
-pub use packet::{Packet, Payload};
+pub use packet::{Packet, Payload, RejectionReason};
+
+pub mod rate_limit;
+pub use rate_limit::{LimitedPayload, RateLimit, RateLimits};
+
+pub mod reputation;
+pub use reputation::{Reputation, ReputationConfig};
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
 
 pub mod brb_data_type;
-pub use brb_data_type::BRBDataType;
+pub use brb_data_type::{BRBDataType, ValidationContext};
+
+pub mod cmrdt;
+pub use cmrdt::{AcceptAll, AuthoredOp, BRBCmRDT, CmRDTOp, SourceIsAuthor, ValidationPolicy};
+
+pub mod kv_store;
+pub use kv_store::{BRBKvStore, KvError, KvOp};
+
+pub mod combinators;
+pub use combinators::{Product, ProductOp, Registry, RegistryOp};
+
+pub mod acl;
+pub use acl::{AccessControlled, Acl, AclEntry, AclOp, AllOps, OpClassifier};
@@ -0,0 +1,342 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Metrics collected by DeterministicBRB, enabled with the `metrics` cargo feature.
+//!
+//! Metrics are exported in the Prometheus text format, see
+//! https://prometheus.io/docs/instrumenting/exposition_formats/
+//!
+//! A node can expose them for local scraping with something like:
+//!
+//! ```ignore
+//! let listener = std::net::TcpListener::bind("127.0.0.1:9898")?;
+//! for stream in listener.incoming() {
+//!     brb::metrics::serve_scrape(stream?, &brb.encode_metrics())?;
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt::{self, Write as FmtWrite};
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use brb_membership::{Actor, Sig};
+use crdts::Dot;
+
+use crate::{Error, Payload, ValidationError};
+
+/// Upper bounds (in seconds) of the commit latency histogram buckets.
+pub const COMMIT_LATENCY_BUCKETS: [f64; 10] =
+    [0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0, 10.0];
+
+/// The maximum size of a scrape request head we will read.
+pub const MAX_SCRAPE_REQUEST_LEN: u64 = 8 * 1024;
+
+/// How long we wait on a scraper to send its request.
+pub const SCRAPE_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The maximum number of msgs we time at once, the oldest is forgotten past it.
+pub const MAX_TIMED_EXECS: usize = 1024;
+
+/// A Prometheus histogram with the buckets in COMMIT_LATENCY_BUCKETS.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    /// number of observations less than or equal to each bucket bound
+    pub buckets: [u64; COMMIT_LATENCY_BUCKETS.len()],
+    /// sum of all observations, in seconds
+    pub sum: f64,
+    /// number of observations
+    pub count: u64,
+}
+
+impl Histogram {
+    /// records an observation
+    pub fn observe(&mut self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (bound, bucket) in COMMIT_LATENCY_BUCKETS.iter().zip(self.buckets.iter_mut()) {
+            if secs <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+}
+
+/// The current size of DeterministicBRB's buffers, sampled when metrics are encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Gauges {
+    /// number of msgs in pending_proof
+    pub pending_proof: usize,
+    /// number of msgs in pending_delivery
+    pub pending_delivery: usize,
+    /// number of msgs in history_from_source, across all sources
+    pub history_len: usize,
+}
+
+/// Counters and timings collected while handling packets.
+#[derive(Debug)]
+pub struct Metrics<A: Ord + Hash> {
+    /// packets handled, by payload kind
+    pub packets_handled: BTreeMap<&'static str, u64>,
+    /// packets rejected, by error variant
+    pub rejections: BTreeMap<&'static str, u64>,
+    /// number of signatures we have verified
+    pub signatures_verified: AtomicU64,
+    /// time from exec_op to a super-majority of delivery confirmations
+    pub commit_latency: Histogram,
+    /// when we initiated each msg that has not yet been committed or abandoned, we
+    /// never see a super-majority of confirmations for some msgs so this is bounded
+    /// by MAX_TIMED_EXECS
+    pub exec_started: HashMap<Dot<A>, Instant>,
+}
+
+impl<A: Ord + Hash> Default for Metrics<A> {
+    /// returns empty Metrics
+    fn default() -> Self {
+        Self {
+            packets_handled: Default::default(),
+            rejections: Default::default(),
+            signatures_verified: Default::default(),
+            commit_latency: Default::default(),
+            exec_started: Default::default(),
+        }
+    }
+}
+
+impl<A: Ord + Hash> Metrics<A> {
+    /// records a packet being handled
+    pub fn record_packet<S: Sig, DataTypeOp>(&mut self, payload: &Payload<A, S, DataTypeOp>)
+    where
+        A: Actor<S>,
+    {
+        *self.packets_handled.entry(payload.kind()).or_default() += 1;
+    }
+
+    /// records a packet being rejected
+    pub fn record_rejection<S: Sig, V: fmt::Debug + error::Error>(&mut self, err: &Error<A, S, V>)
+    where
+        A: Actor<S>,
+    {
+        *self.rejections.entry(error_kind(err)).or_default() += 1;
+    }
+
+    /// records a signature verification
+    pub fn record_signature_verified(&self) {
+        self.signatures_verified.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// records that we initiated the msg with this dot
+    pub fn record_exec(&mut self, dot: Dot<A>)
+    where
+        A: Clone,
+    {
+        if self.exec_started.len() >= MAX_TIMED_EXECS {
+            let oldest = self
+                .exec_started
+                .iter()
+                .min_by_key(|(dot, started)| (**started, dot.counter))
+                .map(|(dot, _)| dot.clone());
+            if let Some(oldest) = oldest {
+                self.exec_started.remove(&oldest);
+            }
+        }
+        self.exec_started.insert(dot, Instant::now());
+    }
+
+    /// records that the msg with this dot has been committed
+    pub fn record_commit(&mut self, dot: &Dot<A>) {
+        if let Some(started) = self.exec_started.remove(dot) {
+            self.commit_latency.observe(started.elapsed());
+        }
+    }
+
+    /// records that the msg with this dot will never be committed
+    pub fn record_abandoned(&mut self, dot: &Dot<A>) {
+        self.exec_started.remove(dot);
+    }
+
+    /// Encodes the metrics in the Prometheus text format.
+    pub fn encode(&self, gauges: &Gauges) -> String {
+        let mut out = String::new();
+        // Writing to a String can not fail.
+        self.write_prometheus(&mut out, gauges).unwrap();
+        out
+    }
+
+    /// Writes the metrics to out in the Prometheus text format.
+    fn write_prometheus(&self, out: &mut String, gauges: &Gauges) -> fmt::Result {
+        writeln!(
+            out,
+            "# HELP brb_packets_handled_total Packets handled, by payload kind."
+        )?;
+        writeln!(out, "# TYPE brb_packets_handled_total counter")?;
+        for (kind, count) in self.packets_handled.iter() {
+            writeln!(
+                out,
+                "brb_packets_handled_total{{kind=\"{}\"}} {}",
+                kind, count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP brb_rejections_total Packets rejected, by error."
+        )?;
+        writeln!(out, "# TYPE brb_rejections_total counter")?;
+        for (reason, count) in self.rejections.iter() {
+            writeln!(
+                out,
+                "brb_rejections_total{{reason=\"{}\"}} {}",
+                reason, count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP brb_signatures_verified_total Signatures verified."
+        )?;
+        writeln!(out, "# TYPE brb_signatures_verified_total counter")?;
+        writeln!(
+            out,
+            "brb_signatures_verified_total {}",
+            self.signatures_verified.load(Ordering::Relaxed)
+        )?;
+
+        for (name, help, value) in [
+            (
+                "brb_pending_proof",
+                "Msgs waiting on a proof of agreement.",
+                gauges.pending_proof,
+            ),
+            (
+                "brb_pending_delivery",
+                "Msgs waiting on delivery confirmations.",
+                gauges.pending_delivery,
+            ),
+            (
+                "brb_history_length",
+                "Msgs delivered, across all sources.",
+                gauges.history_len,
+            ),
+        ]
+        .iter()
+        {
+            writeln!(out, "# HELP {} {}", name, help)?;
+            writeln!(out, "# TYPE {} gauge", name)?;
+            writeln!(out, "{} {}", name, value)?;
+        }
+
+        let latency = &self.commit_latency;
+        writeln!(
+            out,
+            "# HELP brb_commit_latency_seconds Time from exec_op to commit."
+        )?;
+        writeln!(out, "# TYPE brb_commit_latency_seconds histogram")?;
+        for (bound, count) in COMMIT_LATENCY_BUCKETS.iter().zip(latency.buckets.iter()) {
+            writeln!(
+                out,
+                "brb_commit_latency_seconds_bucket{{le=\"{}\"}} {}",
+                bound, count
+            )?;
+        }
+        writeln!(
+            out,
+            "brb_commit_latency_seconds_bucket{{le=\"+Inf\"}} {}",
+            latency.count
+        )?;
+        writeln!(out, "brb_commit_latency_seconds_sum {}", latency.sum)?;
+        writeln!(out, "brb_commit_latency_seconds_count {}", latency.count)
+    }
+}
+
+/// Answers a single HTTP scrape request on stream with the encoded metrics.
+///
+/// Fails if the request head is larger than MAX_SCRAPE_REQUEST_LEN or is not received
+/// within SCRAPE_READ_TIMEOUT.
+pub fn serve_scrape(mut stream: TcpStream, metrics: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(SCRAPE_READ_TIMEOUT))?;
+
+    // We answer every request with the metrics, we only need to consume the request head.
+    let mut reader = BufReader::new((&mut stream).take(MAX_SCRAPE_REQUEST_LEN));
+    let mut line = String::new();
+    loop {
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "scrape request head is incomplete or too large",
+            ));
+        }
+        if line == "\r\n" || line == "\n" {
+            break;
+        }
+        line.clear();
+    }
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        metrics.len(),
+        metrics
+    )?;
+    stream.flush()
+}
+
+/// The label used for an error, validation errors are labelled by variant.
+fn error_kind<A: Actor<S>, S: Sig, V: fmt::Debug + error::Error>(
+    err: &Error<A, S, V>,
+) -> &'static str {
+    match err {
+        Error::Membership(_) => "Membership",
+        Error::Encoding(_) => "Encoding",
+        Error::Signature(_) => "Signature",
+        Error::RateLimited { .. } => "RateLimited",
+        Error::Quarantined { .. } => "Quarantined",
+        Error::Validation(err) => match err {
+            ValidationError::PacketSourceIsNotDot { .. } => "PacketSourceIsNotDot",
+            ValidationError::MsgDotNotTheNextDot { .. } => "MsgDotNotTheNextDot",
+            ValidationError::SourceSentDifferentMsgForSignedDot { .. } => {
+                "SourceSentDifferentMsgForSignedDot"
+            }
+            ValidationError::SourceAlreadyHasPendingMsg { .. } => "SourceAlreadyHasPendingMsg",
+            ValidationError::MessageFromDifferentGeneration { .. } => {
+                "MessageFromDifferentGeneration"
+            }
+            ValidationError::SourceIsNotVotingMember { .. } => "SourceIsNotVotingMember",
+            ValidationError::DataTypeFailedValidation(_) => "DataTypeFailedValidation",
+            ValidationError::InvalidSignature => "InvalidSignature",
+            ValidationError::SignedValidatedForPacketWeDidNotRequest { .. } => {
+                "SignedValidatedForPacketWeDidNotRequest"
+            }
+            ValidationError::MsgDotNotNextDotToBeDelivered { .. } => {
+                "MsgDotNotNextDotToBeDelivered"
+            }
+            ValidationError::NotEnoughSignaturesToFormQuorum { .. } => {
+                "NotEnoughSignaturesToFormQuorum"
+            }
+            ValidationError::ProofContainsSignaturesFromNonMembers { .. } => {
+                "ProofContainsSignaturesFromNonMembers"
+            }
+            ValidationError::ProofContainsInvalidSignatures { .. } => {
+                "ProofContainsInvalidSignatures"
+            }
+            ValidationError::DeliveredForPacketWeDidNotInitiate { .. } => {
+                "DeliveredForPacketWeDidNotInitiate"
+            }
+            ValidationError::DeliveredForPacketWeAreNotWaitingOn { .. } => {
+                "DeliveredForPacketWeAreNotWaitingOn"
+            }
+            ValidationError::PhantomSig(_) => "PhantomSig",
+        },
+    }
+}
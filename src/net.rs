@@ -20,12 +20,43 @@ use std::collections::{BTreeSet, HashMap};
 
 use log::{info, warn};
 use std::fs::File;
-use std::io::Write;
+use std::io;
 
 use crate::brb_data_type::BRBDataType;
-use crate::deterministic_brb::DeterministicBRB;
+use crate::deterministic_brb::{DeterministicBRB, Msg};
+use crate::packet::Payload;
 pub use brb_membership::actor::ed25519::{Actor, Sig, SigningActor};
-use brb_membership::SigningActor as SigningActorTrait;
+use brb_membership::Reconfig;
+
+pub mod byzantine;
+pub use byzantine::{Byzantine, NetResult};
+
+pub mod diagram;
+use diagram::Diagram;
+pub use diagram::{DiagramFilter, DiagramFormat};
+
+pub mod faults;
+pub use faults::{Fault, FaultModel, LinkFaults, Seeds};
+
+pub mod invariants;
+pub use invariants::{InvariantChecker, Property};
+
+pub mod latency;
+pub use latency::{Latency, LatencyModel, LatencyReport, OpTiming, Time, TimeSummary};
+
+pub mod partition;
+pub use partition::{CrossPartition, Partition};
+
+pub mod scenario;
+pub use scenario::{Event, Scenario};
+
+pub mod trace;
+pub use trace::{Divergence, ProcConfig, Step, Trace, TracedFaults, TracedProc};
+
+pub mod scheduler;
+pub use scheduler::{
+    explore, ExploreBounds, Fifo, Pick, RandomScheduler, ReplayScheduler, Scheduler, Violation,
+};
 
 /// A DeterministicBRB specialized to ed25519 types, for use in simulated Network and test cases.
 pub type State<BRBDT> = DeterministicBRB<Actor, SigningActor, Sig, BRBDT>;
@@ -48,6 +79,30 @@ pub struct Net<DT: BRBDT> {
     pub n_packets: u64,
     /// count of invalid packets, by actor.
     pub invalid_packets: HashMap<Actor, u64>,
+    /// if true, procs reply to packets they reject with a Rejected packet.
+    pub send_rejections: bool,
+    /// if set, faults are injected into packets run through `run_packets_to_completion`.
+    pub faults: Option<FaultModel>,
+    /// if set, packets run through `run_packets_to_completion` can't cross the partition.
+    pub partition: Option<Partition>,
+    /// packets held back by a partition with the `CrossPartition::Queue` policy.
+    pub partitioned_packets: Vec<Packet<DT::Op>>,
+    /// the behaviours of byzantine procs, by actor.
+    pub byzantine: HashMap<Actor, Box<dyn Byzantine<DT>>>,
+    /// picks the next packet delivered by `run_packets_to_completion`.
+    pub scheduler: Box<dyn Scheduler>,
+    /// the picks made by the scheduler so far.
+    pub schedule: Vec<Pick>,
+    /// if set, BRB properties are checked as packets are delivered.
+    pub invariants: Option<InvariantChecker>,
+    /// the first invariant found to be violated, if any.
+    pub violation: Option<Violation>,
+    /// if set, the steps taken by the network are recorded.
+    pub trace: Option<Trace<DT::Op>>,
+    /// if set, packets are delayed by the latency of their link and delivered in arrival order.
+    pub latency: Option<LatencyModel>,
+    /// the virtual time, advanced to the arrival time of each packet delivered.
+    pub clock: Time,
 }
 
 impl<DT: BRBDT> Default for Net<DT> {
@@ -65,9 +120,58 @@ impl<DT: BRBDT> Net<DT> {
             n_packets: 0,
             delivered_packets: Default::default(),
             invalid_packets: Default::default(),
+            send_rejections: false,
+            faults: None,
+            partition: None,
+            partitioned_packets: Default::default(),
+            byzantine: Default::default(),
+            scheduler: Box::new(Fifo),
+            schedule: Default::default(),
+            invariants: None,
+            violation: None,
+            trace: None,
+            latency: None,
+            clock: 0,
+        }
+    }
+
+    /// Create a network checking BRB properties as packets are delivered.
+    pub fn with_invariants() -> Self {
+        Self {
+            invariants: Some(Default::default()),
+            ..Self::new()
         }
     }
 
+    /// Create a network delivering packets in the order picked by the given scheduler.
+    pub fn with_scheduler(scheduler: impl Scheduler + 'static) -> Self {
+        Self {
+            scheduler: Box::new(scheduler),
+            ..Self::new()
+        }
+    }
+
+    /// Create a network injecting faults from the given fault model.
+    pub fn with_faults(faults: FaultModel) -> Self {
+        Self {
+            faults: Some(faults),
+            ..Self::new()
+        }
+    }
+
+    /// Create a network delaying packets with the given latency model.
+    pub fn with_latency(latency: LatencyModel) -> Self {
+        Self {
+            latency: Some(latency),
+            ..Self::new()
+        }
+    }
+
+    /// The timings of the ops sent so far, if the network has a latency model.
+    pub fn latency_report(&self) -> Option<LatencyReport> {
+        self.latency.as_ref().map(|l| l.report(self.clock))
+    }
+
     /// The largest set of procs who mutually see each other as peers
     /// are considered to be the network members.
     pub fn members(&self) -> BTreeSet<Actor> {
@@ -99,6 +203,20 @@ impl<DT: BRBDT> Net<DT> {
         actor
     }
 
+    /// Initialize n_procs new processes and force every proc in the network to
+    /// recognise them as members of the genesis generation.
+    pub fn initialize_members(&mut self, n_procs: usize) -> Vec<Actor> {
+        let actors: Vec<_> = (0..n_procs).map(|_| self.initialize_proc()).collect();
+
+        for proc in self.procs.iter_mut() {
+            for actor in actors.iter() {
+                proc.force_join(*actor);
+            }
+        }
+
+        actors
+    }
+
     /// Get a (immutable) reference to a proc with the given actor.
     pub fn proc(&self, actor: &Actor) -> Option<&State<DT>> {
         self.procs
@@ -113,6 +231,81 @@ impl<DT: BRBDT> Net<DT> {
             .find(|secure_p| &secure_p.actor() == actor)
     }
 
+    /// Split the network with the given partition, replacing any current partition.
+    pub fn partition(&mut self, partition: Partition) {
+        info!("[NET] partition {:?}", partition.groups);
+        self.partition = Some(partition);
+    }
+
+    /// Heal the current partition, delivering any packets it held back.
+    pub fn heal(&mut self) {
+        let packets = self.take_healed_packets();
+        self.run_packets_to_completion(packets);
+    }
+
+    fn take_healed_packets(&mut self) -> Vec<Packet<DT::Op>> {
+        if self.partition.take().is_some() {
+            info!("[NET] partition healed");
+        }
+        std::mem::take(&mut self.partitioned_packets)
+    }
+
+    /// Replace the honest behaviour of a proc with a byzantine one.
+    pub fn make_byzantine(&mut self, actor: Actor, behaviour: impl Byzantine<DT> + 'static) {
+        self.byzantine.insert(actor, Box::new(behaviour));
+    }
+
+    /// Execute an op on the proc of the given actor, following its byzantine
+    /// behaviour if it has one.
+    #[allow(clippy::result_large_err)]
+    pub fn exec_op(&mut self, actor: &Actor, op: DT::Op) -> NetResult<DT> {
+        let byzantine = self.byzantine.get_mut(actor);
+        let result = match self.procs.iter_mut().find(|p| &p.actor() == actor) {
+            Some(proc) => match byzantine {
+                Some(behaviour) => behaviour.exec_op(proc, op.clone()),
+                None => proc.exec_op(op.clone()),
+            },
+            None => Ok(vec![]),
+        };
+        if let Some(trace) = self.trace.as_mut() {
+            let (sent, refused) = trace::outcome::<DT>(&result);
+            trace.steps.push(Step::ExecOp {
+                proc: *actor,
+                op,
+                sent,
+                refused,
+            });
+        }
+        result
+    }
+
+    /// Propose a reconfig of the membership from the proc of the given actor.
+    #[allow(clippy::result_large_err)]
+    pub fn propose(&mut self, actor: &Actor, reconfig: Reconfig<Actor>) -> NetResult<DT> {
+        let result = match self.proc_mut(actor) {
+            Some(proc) => match reconfig {
+                Reconfig::Join(peer) => proc.request_membership(peer),
+                Reconfig::Leave(peer) => proc.kill_peer(peer),
+            },
+            None => Ok(vec![]),
+        };
+        if let Some(trace) = self.trace.as_mut() {
+            let (sent, refused) = trace::outcome::<DT>(&result);
+            trace.steps.push(Step::Reconfig {
+                proc: *actor,
+                reconfig,
+                sent,
+                refused,
+            });
+        }
+        result
+    }
+
+    /// Start recording the steps taken by the network, replacing any trace recorded so far.
+    pub fn record_trace(&mut self) {
+        self.trace = Some(Trace::record(self));
+    }
+
     /// Perform anti-entropy corrections on the network.
     /// Currently this is God mode implementations in that we don't
     /// use message passing and we share process state directly.
@@ -142,23 +335,99 @@ impl<DT: BRBDT> Net<DT> {
         self.n_packets += 1;
         let dest = packet.dest;
         self.delivered_packets.push(packet.clone());
-        self.proc_mut(&dest)
-            .map(|p| p.handle_packet(packet))
-            .unwrap_or_else(|| Ok(vec![])) // no proc to deliver too
-            .unwrap_or_else(|err| {
-                warn!("[BRB] Rejected packet: {:?}", err);
-                let count = self.invalid_packets.entry(dest).or_default();
-                *count += 1;
+        let byzantine = self.byzantine.get_mut(&dest);
+        let result = match self.procs.iter_mut().find(|p| p.actor() == dest) {
+            Some(p) => match byzantine {
+                Some(behaviour) => behaviour.handle_packet(p, packet.clone()),
+                None => p.handle_packet(packet.clone()),
+            },
+            None => Ok(vec![]), // no proc to deliver too
+        };
+        let packets = result.unwrap_or_else(|err| {
+            warn!("[BRB] Rejected packet: {:?}", err);
+            let count = self.invalid_packets.entry(dest).or_default();
+            *count += 1;
+            if self.send_rejections {
+                let rejection = match self.proc(&dest) {
+                    Some(p) => p.reject(&packet, &err),
+                    None => Ok(None),
+                };
+                match rejection {
+                    Ok(rejection) => rejection.into_iter().collect(),
+                    Err(reject_err) => {
+                        // Failing to sign a rejection is our fault, not the source's.
+                        // Surface it through the run instead of panicking mid-delivery.
+                        self.violate(format!(
+                            "{} failed to reject packet: {:?}",
+                            dest, reject_err
+                        ));
+                        vec![]
+                    }
+                }
+            } else {
                 vec![]
-            })
+            }
+        });
+        if let Some(trace) = self.trace.as_mut() {
+            trace.steps.push(Step::Deliver {
+                packet,
+                sent: packets.clone(),
+            });
+        }
+        self.check_safety();
+        packets
+    }
+
+    /// Check the safety properties, recording the first violation.
+    fn check_safety(&mut self) {
+        if self.violation.is_some() {
+            return;
+        }
+        if let Some(mut invariants) = self.invariants.take() {
+            if let Err(reason) = invariants.check_safety(self) {
+                self.violate(reason);
+            }
+            self.invariants = Some(invariants);
+        }
+    }
+
+    /// Check that all honest members delivered the same msgs, recording the first violation.
+    fn check_totality(&mut self) {
+        if self.violation.is_some() || !self.partitioned_packets.is_empty() {
+            return;
+        }
+        let result = self.invariants.as_ref().map(|i| i.check_totality(self));
+        if let Some(Err(reason)) = result {
+            self.violate(reason);
+        }
+    }
+
+    fn violate(&mut self, reason: String) {
+        warn!("[NET] invariant violated: {}", reason);
+        self.violation = Some(Violation {
+            step: self.n_packets,
+            schedule: self.schedule.iter().map(|p| p.index).collect(),
+            reason,
+            seeds: self.seeds(),
+        });
+    }
+
+    /// The seeds of the randomized components of the network, to report on failure.
+    pub fn seeds(&self) -> Seeds {
+        Seeds {
+            faults: self.faults.as_ref().map(|faults| faults.seed),
+            scheduler: self.scheduler.seed(),
+            latency: self.latency.as_ref().map(|latency| latency.seed),
+        }
     }
 
-    /// Checks if all members of the network have converged to the same state.
+    /// Checks if all honest members of the network have converged to the same state.
     pub fn members_are_in_agreement(&self) -> bool {
         // Procs are in agreement if the their op histories are identical
         let mut member_states_iter = self
             .members()
             .into_iter()
+            .filter(|actor| !self.byzantine.contains_key(actor))
             .flat_map(|actor| self.proc(&actor))
             .map(|p| &p.history_from_source);
 
@@ -176,56 +445,236 @@ impl<DT: BRBDT> Net<DT> {
 
     /// Convenience function to iteratively deliver all packets along with any packets
     /// that may result from delivering a packet.
-    pub fn run_packets_to_completion(&mut self, mut packets: Vec<Packet<DT::Op>>) {
-        while !packets.is_empty() {
-            let packet = packets.remove(0);
-            packets.extend(self.deliver_packet(packet));
+    ///
+    /// Packets are delivered in the order picked by the network's scheduler, or in order of
+    /// arrival if the network has a latency model.
+    /// If the network has a fault model, packets may be dropped, duplicated or reordered.
+    /// If the network is partitioned, packets crossing the partition are queued or dropped.
+    pub fn run_packets_to_completion(&mut self, packets: Vec<Packet<DT::Op>>) {
+        let _ = self.run_packets_checked(packets, |_, _| Ok(()));
+    }
+
+    /// Run packets to completion like `run_packets_to_completion`, calling `check` with the
+    /// network and the pending packets after every packet taken off the queue.
+    ///
+    /// Stops at the first step `check` fails, or the network's invariants are violated.
+    pub fn run_packets_checked<C>(
+        &mut self,
+        mut packets: Vec<Packet<DT::Op>>,
+        mut check: C,
+    ) -> Result<(), Violation>
+    where
+        C: FnMut(&Self, &[Packet<DT::Op>]) -> Result<(), String>,
+    {
+        let mut arrivals = vec![];
+        self.send(std::mem::take(&mut packets), &mut packets, &mut arrivals);
+        loop {
+            let heal = self.partition.as_ref().map(|p| p.is_healed(self.n_packets));
+            if heal.unwrap_or(false) {
+                let healed = self.take_healed_packets();
+                self.send(healed, &mut packets, &mut arrivals);
+            }
+            if packets.is_empty() {
+                if let Some(report) = self.latency_report() {
+                    info!("[NET] {}", report);
+                }
+                self.check_totality();
+                return self.violation.clone().map_or(Ok(()), Err);
+            }
+
+            let pending = packets.len();
+            let index = match self.latency {
+                Some(_) => (0..pending).min_by_key(|i| arrivals[*i]).unwrap_or(0),
+                None => self.scheduler.pick(pending).min(pending - 1),
+            };
+            self.schedule.push(Pick { index, pending });
+            let packet = packets.remove(index);
+            self.clock = self.clock.max(arrivals.remove(index));
+            self.run_packet(packet, index, &mut packets, &mut arrivals);
+
+            if let Err(reason) = check(self, &packets) {
+                self.violate(reason);
+            }
+            if let Some(violation) = self.violation.clone() {
+                return Err(violation);
+            }
         }
     }
 
+    /// Run a packet taken off the queue at `index`, applying the fault model to it.
+    ///
+    /// With a latency model, packets are delivered in order of arrival rather than queue
+    /// position, so packets are never reordered.
+    fn run_packet(
+        &mut self,
+        packet: Packet<DT::Op>,
+        index: usize,
+        packets: &mut Vec<Packet<DT::Op>>,
+        arrivals: &mut Vec<Time>,
+    ) {
+        if let Some(partition) = self.partition.as_ref() {
+            if !partition.can_reach(&packet.source, &packet.dest) {
+                match partition.policy {
+                    CrossPartition::Queue => self.partitioned_packets.push(packet),
+                    CrossPartition::Drop => info!(
+                        "[NET] partition dropped packet {}->{}",
+                        packet.source, packet.dest
+                    ),
+                }
+                return;
+            }
+        }
+
+        let queued = match self.latency {
+            Some(_) => 0,
+            None => packets.len() - index,
+        };
+        let fault = match self.faults.as_mut() {
+            Some(faults) => faults.decide(&packet.source, &packet.dest, queued),
+            None => Fault::Deliver,
+        };
+        match fault {
+            Fault::Deliver => self.deliver_timed(packet, packets, arrivals),
+            Fault::Drop => info!("[NET] dropped packet {}->{}", packet.source, packet.dest),
+            Fault::Duplicate => {
+                self.send(vec![packet.clone()], packets, arrivals);
+                self.deliver_timed(packet, packets, arrivals);
+            }
+            Fault::Reorder(n) => {
+                arrivals.insert(index + n, self.clock + self.delay(&packet));
+                packets.insert(index + n, packet);
+            }
+        }
+    }
+
+    /// Deliver a packet, sending the packets it produces at the current time.
+    fn deliver_timed(
+        &mut self,
+        packet: Packet<DT::Op>,
+        packets: &mut Vec<Packet<DT::Op>>,
+        arrivals: &mut Vec<Time>,
+    ) {
+        // a packet may deliver msgs replayed from the reorder buffer, not only its own
+        let dest = packet.dest;
+        let before = match self.latency {
+            Some(_) => self.proc(&dest).map(|proc| proc.delivered.clone()),
+            None => None,
+        };
+        let sent = self.deliver_packet(packet);
+        let delivered: Vec<_> = match (before, self.proc(&dest)) {
+            (Some(before), Some(proc)) => proc
+                .history_from_source
+                .values()
+                .flatten()
+                .map(|(msg, ..)| msg)
+                .filter(|msg| msg.dot.counter > before.get(&msg.dot.actor))
+                .cloned()
+                .collect(),
+            _ => vec![],
+        };
+        for msg in delivered {
+            self.time_delivery(&dest, &msg);
+        }
+        self.send(sent, packets, arrivals);
+    }
+
+    /// Queue packets sent at the current time, timing the ops they carry.
+    fn send(
+        &mut self,
+        sent: Vec<Packet<DT::Op>>,
+        packets: &mut Vec<Packet<DT::Op>>,
+        arrivals: &mut Vec<Time>,
+    ) {
+        let clock = self.clock;
+        for packet in sent {
+            arrivals.push(clock + self.delay(&packet));
+            if let (Some(latency), Payload::BRB(op)) = (self.latency.as_mut(), &packet.payload) {
+                let dot = op.msg().dot;
+                let timing = latency
+                    .ops
+                    .entry((dot.actor, dot.counter))
+                    .or_insert_with(|| OpTiming {
+                        sent: clock,
+                        ..Default::default()
+                    });
+                if packet.payload.is_proof_of_agreement() && timing.proof.is_none() {
+                    timing.proof = Some(clock);
+                }
+            }
+            packets.push(packet);
+        }
+    }
+
+    /// the delay of a packet drawn from the latency model, 0 without one.
+    fn delay(&mut self, packet: &Packet<DT::Op>) -> Time {
+        self.latency
+            .as_mut()
+            .map(|latency| latency.delay(&packet.source, &packet.dest))
+            .unwrap_or(0)
+    }
+
+    /// Record an honest proc delivering a msg, and the time a supermajority of its
+    /// generation has delivered it.
+    fn time_delivery(&mut self, actor: &Actor, msg: &Msg<Actor, DT::Op>) {
+        if self.byzantine.contains_key(actor) {
+            return;
+        }
+        let (delivered, members) = match self.proc(actor) {
+            Some(proc) => (
+                proc.delivered.get(&msg.dot.actor) >= msg.dot.counter,
+                proc.membership.members(msg.gen).map(|m| m.len()),
+            ),
+            None => return,
+        };
+        let clock = self.clock;
+        let timing = self
+            .latency
+            .as_mut()
+            .and_then(|latency| latency.ops.get_mut(&(msg.dot.actor, msg.dot.counter)));
+        if let (true, Ok(members), Some(timing)) = (delivered, members, timing) {
+            timing.delivered_by.insert(*actor);
+            if timing.supermajority.is_none() && timing.delivered_by.len() * 3 > members * 2 {
+                timing.supermajority = Some(clock);
+            }
+        }
+    }
+
+    /// Returns a sequence diagram of the delivered packets selected by `filter`.
+    pub fn diagram(&self, format: DiagramFormat, filter: &DiagramFilter) -> String {
+        let mut bytes = vec![];
+        self.write_diagram(&mut bytes, format, filter)
+            .expect("Failed to write diagram to memory");
+        String::from_utf8(bytes).expect("Diagram is not valid utf8")
+    }
+
+    /// Writes a sequence diagram of the delivered packets selected by `filter`.
+    pub fn write_diagram(
+        &self,
+        w: &mut impl io::Write,
+        format: DiagramFormat,
+        filter: &DiagramFilter,
+    ) -> io::Result<()> {
+        // procs are drawn as 1, 2, 3 ... instead of i:3b2, i:7def, ...
+        let numbers = self
+            .procs
+            .iter()
+            .enumerate()
+            .map(|(idx, p)| (p.actor(), idx + 1))
+            .collect();
+        let diagram = Diagram {
+            numbers,
+            format,
+            filter,
+        };
+        diagram.write(w, &self.delivered_packets)
+    }
+
     /// Generates an MSC file representing a packet sequence diagram.
     /// See http://www.mcternan.me.uk/mscgen/
     /// See https://github.com/maidsafe/brb_membership#tests
     pub fn generate_msc(&self, chart_name: &str) {
-        // See: http://www.mcternan.me.uk/mscgen/
-        let mut msc = String::from(
-            "
-msc {\n
-  hscale = \"2\";\n
-",
-        );
-        let procs = self
-            .procs
-            .iter()
-            .map(|p| p.membership.id.actor())
-            .collect::<BTreeSet<_>>() // sort by actor id
-            .into_iter()
-            .map(|id| format!("{:?}", id))
-            .collect::<Vec<_>>()
-            .join(",");
-        msc.push_str(&procs);
-        msc.push_str(";\n");
-        for packet in self.delivered_packets.iter() {
-            msc.push_str(&format!(
-                "{}->{} [ label=\"{:?}\"];\n",
-                packet.source, packet.dest, packet.payload
-            ));
-        }
-
-        msc.push_str("}\n");
-
-        // Replace process identifiers with friendlier numbers
-        // 1, 2, 3 ... instead of i:3b2, i:7def, ...
-        for (idx, proc_id) in self
-            .procs
-            .iter()
-            .map(|p| p.membership.id.actor())
-            .enumerate()
-        {
-            let proc_id_as_str = format!("{}", proc_id);
-            msc = msc.replace(&proc_id_as_str, &format!("{}", idx + 1));
-        }
         let mut msc_file = File::create(format!("{}.msc", chart_name)).unwrap();
-        msc_file.write_all(msc.as_bytes()).unwrap();
+        self.write_diagram(&mut msc_file, DiagramFormat::Msc, &Default::default())
+            .unwrap();
     }
 }
@@ -0,0 +1,216 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Byzantine behaviours for procs in the simulated network.
+//!
+//! A proc made byzantine with `Net::make_byzantine` keeps its keys and BRB state, but
+//! the packets it receives and the ops it executes through `Net::exec_op` are handled by
+//! its Byzantine behaviour instead of the honest DeterministicBRB logic.
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+
+use brb_membership::{Ballot, Reconfig, Vote};
+use crdts::CmRDT;
+use ed25519::{PublicKey, SecretKey};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{Actor, Packet, State, BRBDT};
+use crate::deterministic_brb::{Msg, Op};
+use crate::packet::Payload;
+use crate::Error;
+
+/// The result of handling a packet or an op in the simulated network.
+pub type NetResult<DT> =
+    Result<Vec<Packet<<DT as crate::BRBDataType<super::Actor>>::Op>>, NetError<DT>>;
+
+/// An error from a proc in the simulated network.
+pub type NetError<DT> =
+    Error<super::Actor, super::Sig, <DT as crate::BRBDataType<super::Actor>>::ValidationError>;
+
+/// The behaviour of a byzantine proc.
+pub trait Byzantine<DT: BRBDT>: Debug {
+    /// Handle a packet sent to the byzantine proc, by default it's handled honestly.
+    #[allow(clippy::result_large_err)]
+    fn handle_packet(&mut self, proc: &mut State<DT>, packet: Packet<DT::Op>) -> NetResult<DT> {
+        proc.handle_packet(packet)
+    }
+
+    /// Execute an op as the byzantine proc, by default it's executed honestly.
+    #[allow(clippy::result_large_err)]
+    fn exec_op(&mut self, proc: &mut State<DT>, op: DT::Op) -> NetResult<DT> {
+        proc.exec_op(op)
+    }
+}
+
+/// Never sends a packet.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Silent;
+
+impl<DT: BRBDT> Byzantine<DT> for Silent {
+    fn handle_packet(&mut self, _proc: &mut State<DT>, _packet: Packet<DT::Op>) -> NetResult<DT> {
+        Ok(vec![])
+    }
+
+    fn exec_op(&mut self, _proc: &mut State<DT>, _op: DT::Op) -> NetResult<DT> {
+        Ok(vec![])
+    }
+}
+
+/// Signs every msg it's asked to validate, without validating it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SignEverything;
+
+impl<DT: BRBDT> Byzantine<DT> for SignEverything {
+    fn handle_packet(&mut self, proc: &mut State<DT>, packet: Packet<DT::Op>) -> NetResult<DT> {
+        match packet.payload {
+            Payload::BRB(Op::RequestValidation { msg }) => {
+                let sig = proc.sign(&msg)?;
+                let payload = Payload::BRB(Op::SignedValidated { msg, sig });
+                Ok(vec![proc.send(packet.source, payload)?])
+            }
+            _ => proc.handle_packet(packet),
+        }
+    }
+}
+
+/// Sends a different op to each half of the network under the same dot.
+#[derive(Debug)]
+pub struct Equivocate<DT: BRBDT> {
+    /// the op sent to the second half of the network
+    pub alternative: DT::Op,
+}
+
+impl<DT: BRBDT> Byzantine<DT> for Equivocate<DT> {
+    fn exec_op(&mut self, proc: &mut State<DT>, op: DT::Op) -> NetResult<DT> {
+        let gen = proc.membership.gen;
+        let dot = proc.received.inc(proc.actor());
+        let peers: Vec<_> = proc.peers()?.into_iter().collect();
+        let (first_half, second_half) = peers.split_at(peers.len() / 2);
+
+        let request = |op| {
+            Payload::BRB(Op::RequestValidation {
+                msg: Msg { gen, op, dot },
+            })
+        };
+        let mut packets = proc.broadcast(&request(op), first_half.iter().copied().collect())?;
+        packets.extend(proc.broadcast(
+            &request(self.alternative.clone()),
+            second_half.iter().copied().collect(),
+        )?);
+        Ok(packets)
+    }
+}
+
+/// Sends proofs of agreement signed only by itself.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ForgeProof;
+
+impl<DT: BRBDT> Byzantine<DT> for ForgeProof {
+    fn exec_op(&mut self, proc: &mut State<DT>, op: DT::Op) -> NetResult<DT> {
+        let msg = Msg {
+            gen: proc.membership.gen,
+            op,
+            dot: proc.received.inc(proc.actor()),
+        };
+        // Honest procs bump received when validating their own msg, we skip validation.
+        proc.received.apply(msg.dot);
+        let proof = vec![(proc.actor(), proc.sign(&msg)?)].into_iter().collect();
+        let payload = Payload::BRB(Op::ProofOfAgreement { msg, proof });
+        proc.broadcast(&payload, proc.peers()?)
+    }
+}
+
+/// Handles packets honestly, but sends every packet it sends a second time later on.
+#[derive(Debug)]
+pub struct Replay<DT: BRBDT> {
+    /// packets waiting to be replayed
+    pub sent: VecDeque<Packet<DT::Op>>,
+}
+
+impl<DT: BRBDT> Default for Replay<DT> {
+    fn default() -> Self {
+        Self {
+            sent: Default::default(),
+        }
+    }
+}
+
+impl<DT: BRBDT> Replay<DT> {
+    fn replay(&mut self, mut packets: Vec<Packet<DT::Op>>) -> Vec<Packet<DT::Op>> {
+        let replayed = self.sent.pop_front();
+        self.sent.extend(packets.iter().cloned());
+        packets.extend(replayed);
+        packets
+    }
+}
+
+impl<DT: BRBDT> Byzantine<DT> for Replay<DT> {
+    fn handle_packet(&mut self, proc: &mut State<DT>, packet: Packet<DT::Op>) -> NetResult<DT> {
+        proc.handle_packet(packet)
+            .map(|packets| self.replay(packets))
+    }
+
+    fn exec_op(&mut self, proc: &mut State<DT>, op: DT::Op) -> NetResult<DT> {
+        proc.exec_op(op).map(|packets| self.replay(packets))
+    }
+}
+
+/// Handles packets honestly, but answers each one with membership votes for a
+/// generation far in the future, proposing to add an actor that does not exist.
+///
+/// The actors proposed are drawn from an RNG seeded with `seed`, so runs are reproducible.
+#[derive(Debug)]
+pub struct GarbageVotes {
+    rng: StdRng,
+}
+
+/// How far ahead of its generation a GarbageVotes proc votes.
+pub const GARBAGE_VOTE_GENERATION_SKIP: u64 = 8;
+
+impl GarbageVotes {
+    /// Create a GarbageVotes behaviour seeded with `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Default for GarbageVotes {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<DT: BRBDT> Byzantine<DT> for GarbageVotes {
+    fn handle_packet(&mut self, proc: &mut State<DT>, packet: Packet<DT::Op>) -> NetResult<DT> {
+        let mut packets = proc.handle_packet(packet)?;
+
+        // Any 32 bytes make a valid secret key.
+        let secret = match SecretKey::from_bytes(&self.rng.gen::<[u8; 32]>()) {
+            Ok(secret) => secret,
+            Err(_) => return Ok(packets),
+        };
+        let gen = proc.membership.gen + GARBAGE_VOTE_GENERATION_SKIP + 1;
+        let ballot = Ballot::Propose(Reconfig::Join(Actor(PublicKey::from(&secret))));
+        let vote = Vote {
+            sig: proc.sign((&ballot, &gen))?,
+            voter: proc.actor(),
+            ballot,
+            gen,
+        };
+
+        for member in proc.peers()? {
+            let payload = Payload::Membership(Box::new(vote.clone()));
+            packets.push(proc.send(member, payload)?);
+        }
+        Ok(packets)
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Sequence diagrams of the packets delivered by the simulated network.
+//!
+//! Procs are numbered 1, 2, 3 ... in the order they were initialized, and packets are
+//! labelled by payload kind, e.g. `proof_of_agreement 2.1 (3 sigs)` for a proof over
+//! the first msg from proc 2.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use super::{Actor, Packet};
+use crate::deterministic_brb::Op;
+use crate::packet::Payload;
+
+/// The format of a sequence diagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramFormat {
+    /// mscgen, see http://www.mcternan.me.uk/mscgen/
+    Msc,
+    /// Mermaid, see https://mermaid-js.github.io/mermaid/#/sequenceDiagram
+    Mermaid,
+    /// PlantUML, see https://plantuml.com/sequence-diagram
+    PlantUml,
+}
+
+/// Selects the packets drawn in a diagram, every packet is drawn by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiagramFilter {
+    /// if set, only packets sent from or to these actors are drawn
+    pub actors: Option<BTreeSet<Actor>>,
+    /// if set, only packets with these payload kinds are drawn, see `Payload::kind`
+    pub kinds: Option<BTreeSet<&'static str>>,
+    /// if set, only packets delivered at these steps are drawn, the first packet is step 0
+    pub steps: Option<Range<usize>>,
+}
+
+impl DiagramFilter {
+    /// true if the packet delivered at `step` is drawn.
+    pub fn matches<Op>(&self, step: usize, packet: &Packet<Op>) -> bool {
+        let actor_matches = match &self.actors {
+            Some(actors) => actors.contains(&packet.source) || actors.contains(&packet.dest),
+            None => true,
+        };
+        let kind_matches = match &self.kinds {
+            Some(kinds) => kinds.contains(packet.payload.kind()),
+            None => true,
+        };
+        let step_matches = match &self.steps {
+            Some(steps) => steps.contains(&step),
+            None => true,
+        };
+        actor_matches && kind_matches && step_matches
+    }
+}
+
+/// Draws packets between the given procs as a sequence diagram.
+pub(crate) struct Diagram<'a> {
+    /// the number of each proc, starting from 1
+    pub(crate) numbers: BTreeMap<Actor, usize>,
+    pub(crate) format: DiagramFormat,
+    pub(crate) filter: &'a DiagramFilter,
+}
+
+impl<'a> Diagram<'a> {
+    pub(crate) fn write<DataTypeOp: Debug>(
+        &self,
+        w: &mut impl Write,
+        packets: &[Packet<DataTypeOp>],
+    ) -> io::Result<()> {
+        self.write_header(w)?;
+        for (step, packet) in packets.iter().enumerate() {
+            if !self.filter.matches(step, packet) {
+                continue;
+            }
+            let (source, dest) = (self.number(&packet.source), self.number(&packet.dest));
+            let label = self.label(&packet.payload);
+            match self.format {
+                DiagramFormat::Msc => writeln!(
+                    w,
+                    "  {}->{} [ label=\"{}\"];",
+                    source,
+                    dest,
+                    label.replace('"', "\\\"")
+                )?,
+                DiagramFormat::Mermaid => writeln!(
+                    w,
+                    "  p{}->>p{}: {}",
+                    source,
+                    dest,
+                    label.replace(';', ",").replace('#', "")
+                )?,
+                DiagramFormat::PlantUml => writeln!(w, "p{} -> p{} : {}", source, dest, label)?,
+            }
+        }
+        self.write_footer(w)
+    }
+
+    fn write_header(&self, w: &mut impl Write) -> io::Result<()> {
+        match self.format {
+            DiagramFormat::Msc => {
+                writeln!(w, "msc {{")?;
+                writeln!(w, "  hscale = \"2\";")?;
+                let procs: Vec<_> = self
+                    .by_number()
+                    .into_iter()
+                    .map(|(_, n)| n.to_string())
+                    .collect();
+                writeln!(w, "  {};", procs.join(","))
+            }
+            DiagramFormat::Mermaid => {
+                writeln!(w, "sequenceDiagram")?;
+                for (actor, n) in self.by_number() {
+                    writeln!(w, "  participant p{} as {} ({})", n, n, actor)?;
+                }
+                Ok(())
+            }
+            DiagramFormat::PlantUml => {
+                writeln!(w, "@startuml")?;
+                for (actor, n) in self.by_number() {
+                    writeln!(w, "participant \"{} ({})\" as p{}", n, actor, n)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn write_footer(&self, w: &mut impl Write) -> io::Result<()> {
+        match self.format {
+            DiagramFormat::Msc => writeln!(w, "}}"),
+            DiagramFormat::Mermaid => Ok(()),
+            DiagramFormat::PlantUml => writeln!(w, "@enduml"),
+        }
+    }
+
+    fn by_number(&self) -> Vec<(Actor, usize)> {
+        let mut procs: Vec<_> = self.numbers.iter().map(|(a, n)| (*a, *n)).collect();
+        procs.sort_by_key(|(_, n)| *n);
+        procs
+    }
+
+    /// the number of a proc, or 0 if the packet is for an actor outside the network.
+    fn number(&self, actor: &Actor) -> usize {
+        self.numbers.get(actor).copied().unwrap_or(0)
+    }
+
+    fn label<DataTypeOp: Debug>(&self, payload: &Payload<Actor, super::Sig, DataTypeOp>) -> String {
+        let kind = payload.kind();
+        match payload {
+            Payload::BRB(op) => {
+                let dot = format!(
+                    "{}.{}",
+                    self.number(&op.msg().dot.actor),
+                    op.msg().dot.counter
+                );
+                match op {
+                    Op::RequestValidation { msg } => format!("{} {} {:?}", kind, dot, msg.op),
+                    Op::ProofOfAgreement { proof, .. } => {
+                        format!("{} {} ({} sigs)", kind, dot, proof.len())
+                    }
+                    Op::SignedValidated { .. } | Op::Delivered { .. } => {
+                        format!("{} {}", kind, dot)
+                    }
+                }
+            }
+            Payload::AntiEntropy { generation, .. } => format!("{} gen {}", kind, generation),
+            Payload::Membership(vote) => format!("{} gen {}", kind, vote.gen),
+            Payload::Rejected { reason, dot } => match dot {
+                Some(dot) => format!(
+                    "{} {:?} {}.{}",
+                    kind,
+                    reason,
+                    self.number(&dot.actor),
+                    dot.counter
+                ),
+                None => format!("{} {:?}", kind, reason),
+            },
+        }
+    }
+}
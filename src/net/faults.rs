@@ -0,0 +1,228 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! A seeded fault model for the simulated network.
+//!
+//! Every fault decision is drawn from an RNG seeded with `FaultModel::seed`. The seed is
+//! logged when the model is created and reported by `Net::seeds`, which tests include in
+//! their failures and invariant violations. Setting `BRB_NET_SEED` to it makes
+//! `FaultModel::from_env` replay the same decisions for the same sequence of packets.
+
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Once;
+
+use log::info;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::Actor;
+
+/// The environment variable read by the `from_env` constructors of the randomized components.
+pub const SEED_ENV_VAR: &str = "BRB_NET_SEED";
+
+/// The seed read from `BRB_NET_SEED`, or a random seed if it's not set.
+///
+/// The random seed is drawn once per process, so that every randomized component of a
+/// run created with `from_env` shares the seed reported on failure.
+pub fn env_seed() -> u64 {
+    static SEED: AtomicU64 = AtomicU64::new(0);
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let seed = env::var(SEED_ENV_VAR)
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(rand::random);
+        SEED.store(seed, Ordering::SeqCst);
+    });
+    SEED.load(Ordering::SeqCst)
+}
+
+/// The seeds of the randomized components of a network, to report on failure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Seeds {
+    /// the seed of the fault model, if the network has one
+    pub faults: Option<u64>,
+    /// the seed of the scheduler, if it is randomized
+    pub scheduler: Option<u64>,
+    /// the seed of the latency model, if the network has one
+    pub latency: Option<u64>,
+}
+
+impl fmt::Display for Seeds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seeds: Vec<_> = [
+            ("fault model", self.faults),
+            ("scheduler", self.scheduler),
+            ("latency model", self.latency),
+        ]
+        .iter()
+        .filter_map(|(name, seed)| seed.map(|seed| format!("{} seed {}", name, seed)))
+        .collect();
+        if seeds.is_empty() {
+            write!(f, "no seeds")?;
+        } else {
+            write!(f, "{}", seeds.join(", "))?;
+        }
+        write!(f, " (set {} to replay)", SEED_ENV_VAR)
+    }
+}
+
+/// The default number of queued packets a reordered packet may be delayed behind.
+pub const DEFAULT_REORDER_WINDOW: usize = 4;
+
+/// Probabilities of each fault happening to a packet sent over a link.
+/// Probabilities range over [0, 1], `duplicate` must be below 1 or a packet is duplicated forever.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct LinkFaults {
+    /// probability that a packet is lost
+    pub drop: f64,
+    /// probability that a packet is delivered twice
+    pub duplicate: f64,
+    /// probability that a packet is delayed behind packets queued after it
+    pub reorder: f64,
+}
+
+/// Counts of the faults injected so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FaultStats {
+    /// packets that were lost
+    pub dropped: u64,
+    /// packets that were delivered twice
+    pub duplicated: u64,
+    /// packets that were delayed
+    pub reordered: u64,
+}
+
+/// What to do with a packet taken off the network queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Fault {
+    /// deliver the packet as is
+    Deliver,
+    /// lose the packet
+    Drop,
+    /// deliver the packet and queue a copy of it
+    Duplicate,
+    /// queue the packet again, `n` places behind where it was taken off the queue.
+    /// Never decided when a latency model orders delivery.
+    Reorder(usize),
+}
+
+/// A seeded, configurable fault model.
+#[derive(Debug, Clone)]
+pub struct FaultModel {
+    /// the seed of the RNG making fault decisions
+    pub seed: u64,
+    /// faults on links without an override
+    pub default_faults: LinkFaults,
+    /// per-link overrides, by (source, dest)
+    pub links: HashMap<(Actor, Actor), LinkFaults>,
+    /// the number of queued packets a reordered packet may be delayed behind
+    pub reorder_window: usize,
+    /// counts of the faults injected so far
+    pub stats: FaultStats,
+    /// faults injected into the next packets, in order, before any are drawn from the RNG
+    pub script: VecDeque<Fault>,
+    rng: StdRng,
+}
+
+impl FaultModel {
+    /// Create a fault model seeded with `seed`.
+    pub fn new(seed: u64, default_faults: LinkFaults) -> Self {
+        info!(
+            "[NET] fault model seed: {} (set {} to replay)",
+            seed, SEED_ENV_VAR
+        );
+        Self {
+            seed,
+            default_faults,
+            links: Default::default(),
+            reorder_window: DEFAULT_REORDER_WINDOW,
+            stats: Default::default(),
+            script: Default::default(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Create a fault model injecting the given faults into the next packets, in order,
+    /// and no faults after that.
+    pub fn scripted(script: impl IntoIterator<Item = Fault>) -> Self {
+        Self {
+            seed: 0,
+            default_faults: Default::default(),
+            links: Default::default(),
+            reorder_window: DEFAULT_REORDER_WINDOW,
+            stats: Default::default(),
+            script: script.into_iter().collect(),
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    /// Create a fault model seeded from `BRB_NET_SEED`, see `env_seed`.
+    pub fn from_env(default_faults: LinkFaults) -> Self {
+        Self::new(env_seed(), default_faults)
+    }
+
+    /// Override the faults on the link from `source` to `dest`.
+    pub fn with_link(mut self, source: Actor, dest: Actor, faults: LinkFaults) -> Self {
+        self.links.insert((source, dest), faults);
+        self
+    }
+
+    /// the faults on the link from `source` to `dest`.
+    pub fn link(&self, source: &Actor, dest: &Actor) -> LinkFaults {
+        self.links
+            .get(&(*source, *dest))
+            .copied()
+            .unwrap_or(self.default_faults)
+    }
+
+    /// Decide the fate of a packet sent from `source` to `dest`, `queued` is the
+    /// number of packets queued behind it.
+    pub fn decide(&mut self, source: &Actor, dest: &Actor, queued: usize) -> Fault {
+        if let Some(fault) = self.script.pop_front() {
+            return self.scripted_fault(fault, queued);
+        }
+        let faults = self.link(source, dest);
+        if self.rng.gen_bool(faults.drop) {
+            self.stats.dropped += 1;
+            Fault::Drop
+        } else if self.rng.gen_bool(faults.duplicate) {
+            self.stats.duplicated += 1;
+            Fault::Duplicate
+        } else if queued > 0 && self.reorder_window > 0 && self.rng.gen_bool(faults.reorder) {
+            self.stats.reordered += 1;
+            let window = self.reorder_window.min(queued);
+            Fault::Reorder(self.rng.gen_range(1..=window))
+        } else {
+            Fault::Deliver
+        }
+    }
+
+    fn scripted_fault(&mut self, fault: Fault, queued: usize) -> Fault {
+        match fault {
+            Fault::Deliver => Fault::Deliver,
+            Fault::Drop => {
+                self.stats.dropped += 1;
+                Fault::Drop
+            }
+            Fault::Duplicate => {
+                self.stats.duplicated += 1;
+                Fault::Duplicate
+            }
+            Fault::Reorder(_) if queued == 0 => Fault::Deliver,
+            Fault::Reorder(n) => {
+                self.stats.reordered += 1;
+                Fault::Reorder(n.clamp(1, queued))
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! A checker for BRB properties, run by the simulated network as packets are delivered.
+//!
+//! The safety properties are checked over the honest procs after every `Net::deliver_packet`:
+//! - Agreement: no two different msgs are delivered for the same dot.
+//! - Integrity: a proc only delivers msgs it has received.
+//! - Validity: every delivered msg has a valid proof for its generation.
+//!
+//! Totality, i.e. all honest members eventually deliver the same msgs, is checked once
+//! `Net::run_packets_to_completion` runs out of packets.
+//!
+//! Delivered msgs are only checked once, so checking after every packet stays cheap.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use brb_membership::signature::Verifier;
+
+use super::{Actor, Net, Sig, State, BRBDT};
+use crate::deterministic_brb::Msg;
+
+/// A BRB property checked by the InvariantChecker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Property {
+    /// no two different msgs are delivered for the same dot
+    Agreement,
+    /// a proc only delivers msgs it has received
+    Integrity,
+    /// every delivered msg has a valid proof for its generation
+    Validity,
+    /// all honest members eventually deliver the same msgs
+    Totality,
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Checks the BRB properties over the honest procs of a network.
+#[derive(Debug, Clone)]
+pub struct InvariantChecker {
+    /// if false, Totality is not checked, e.g. when packets may be dropped
+    pub check_totality: bool,
+    /// the serialized msg delivered for each dot so far, by source and counter
+    delivered: BTreeMap<(Actor, u64), Vec<u8>>,
+    /// the number of msgs already checked, by proc and source
+    checked: BTreeMap<(Actor, Actor), usize>,
+}
+
+impl Default for InvariantChecker {
+    fn default() -> Self {
+        Self {
+            check_totality: true,
+            delivered: Default::default(),
+            checked: Default::default(),
+        }
+    }
+}
+
+impl InvariantChecker {
+    /// Check the safety properties over the honest procs of the network.
+    pub fn check_safety<DT: BRBDT>(&mut self, net: &Net<DT>) -> Result<(), String> {
+        for proc in net.procs.iter() {
+            if !net.byzantine.contains_key(&proc.actor()) {
+                self.check_proc(proc)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that all honest members of the network have delivered the same msgs.
+    pub fn check_totality<DT: BRBDT>(&self, net: &Net<DT>) -> Result<(), String> {
+        if !self.check_totality {
+            return Ok(());
+        }
+        let honest: BTreeSet<_> = net
+            .members()
+            .into_iter()
+            .filter(|actor| !net.byzantine.contains_key(actor))
+            .collect();
+        for (source, counter) in self.delivered.keys() {
+            for actor in honest.iter() {
+                let delivered = net.proc(actor).map(|p| p.delivered.get(source));
+                if delivered.unwrap_or(0) < *counter {
+                    return Err(format!(
+                        "{}: {} has not delivered {}.{}",
+                        Property::Totality,
+                        actor,
+                        source,
+                        counter
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_proc<DT: BRBDT>(&mut self, proc: &State<DT>) -> Result<(), String> {
+        let actor = proc.actor();
+        for (source, history) in proc.history_from_source.iter() {
+            let checked = self.checked.entry((actor, *source)).or_default();
+            for (msg, proof, _outcome) in history.iter().skip(*checked) {
+                let bytes = bincode::serialize(msg).map_err(|err| err.to_string())?;
+
+                let previous = self
+                    .delivered
+                    .entry((msg.dot.actor, msg.dot.counter))
+                    .or_insert_with(|| bytes.clone());
+                if previous != &bytes {
+                    return Err(format!(
+                        "{}: {} delivered a different msg for {:?}",
+                        Property::Agreement,
+                        actor,
+                        msg.dot
+                    ));
+                }
+                if &msg.dot.actor != source || proc.received.get(source) < msg.dot.counter {
+                    return Err(format!(
+                        "{}: {} delivered {:?} without receiving it",
+                        Property::Integrity,
+                        actor,
+                        msg.dot
+                    ));
+                }
+                check_proof(proc, msg, &bytes, proof).map_err(|reason| {
+                    format!(
+                        "{}: {} delivered {:?} with {}",
+                        Property::Validity,
+                        actor,
+                        msg.dot,
+                        reason
+                    )
+                })?;
+                *checked += 1;
+            }
+            if proc.delivered.get(source) > proc.received.get(source) {
+                return Err(format!(
+                    "{}: {} delivered more msgs from {} than it received",
+                    Property::Integrity,
+                    actor,
+                    source
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_proof<DT: BRBDT>(
+    proc: &State<DT>,
+    msg: &Msg<Actor, DT::Op>,
+    bytes: &[u8],
+    proof: &BTreeMap<Actor, Sig>,
+) -> Result<(), String> {
+    let members = proc
+        .membership
+        .members(msg.gen)
+        .map_err(|_| format!("a proof for unknown generation {}", msg.gen))?;
+    if proof.len() * 3 <= members.len() * 2 {
+        Err("a proof without a supermajority".to_string())
+    } else if let Some(signer) = proof.keys().find(|signer| !members.contains(signer)) {
+        Err(format!("a proof signed by non-member {}", signer))
+    } else if let Some(signer) = proof
+        .iter()
+        .find(|(signer, sig)| signer.verify(bytes, sig).is_err())
+        .map(|(signer, _)| signer)
+    {
+        Err(format!("a proof with an invalid signature from {}", signer))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! A seeded latency model and virtual clock for the simulated network.
+//!
+//! Every packet sent is given an arrival time of `Net::clock` plus a delay drawn from the
+//! latency of its link. With a latency model, `Net::run_packets_to_completion` delivers
+//! packets in arrival order instead of asking the scheduler, advancing the clock to the
+//! arrival time of each packet delivered.
+//!
+//! The model also times every op sent through the network: when its proof of agreement
+//! was sent and when a supermajority of its generation delivered it.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use log::info;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::faults::{env_seed, SEED_ENV_VAR};
+use super::Actor;
+
+/// Virtual time, in ticks, e.g. milliseconds.
+pub type Time = u64;
+
+/// The distribution of the delay of packets sent over a link.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Latency {
+    /// every packet takes the same time
+    Fixed(Time),
+    /// delays are drawn uniformly from `min..=max`
+    Uniform {
+        /// the shortest delay
+        min: Time,
+        /// the longest delay
+        max: Time,
+    },
+    /// delays are `min` plus an exponentially distributed tail
+    Exponential {
+        /// the shortest delay
+        min: Time,
+        /// the mean of the delay above `min`
+        mean: f64,
+    },
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Latency::Fixed(0)
+    }
+}
+
+impl Latency {
+    /// Draw a delay from this distribution.
+    pub fn sample(&self, rng: &mut impl Rng) -> Time {
+        match *self {
+            Latency::Fixed(delay) => delay,
+            Latency::Uniform { min, max } => rng.gen_range(min..=max.max(min)),
+            Latency::Exponential { min, mean } => {
+                let u: f64 = rng.gen();
+                min + (-(1.0 - u).ln() * mean).round() as Time
+            }
+        }
+    }
+}
+
+/// The times an op took to make its way through the network.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OpTiming {
+    /// when the op was first sent for validation
+    pub sent: Time,
+    /// when its proof of agreement was first sent
+    pub proof: Option<Time>,
+    /// when a supermajority of its generation had delivered it
+    pub supermajority: Option<Time>,
+    /// the honest procs that delivered it
+    pub delivered_by: BTreeSet<Actor>,
+}
+
+impl OpTiming {
+    /// the time from sending the op to sending its proof of agreement.
+    pub fn time_to_proof(&self) -> Option<Time> {
+        self.proof.map(|proof| proof - self.sent)
+    }
+
+    /// the time from sending the op to a supermajority delivering it.
+    pub fn time_to_supermajority(&self) -> Option<Time> {
+        self.supermajority.map(|time| time - self.sent)
+    }
+}
+
+/// The shortest, mean and longest of a set of times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSummary {
+    /// the number of times
+    pub count: usize,
+    /// the shortest time
+    pub min: Time,
+    /// the mean time, rounded down
+    pub mean: Time,
+    /// the longest time
+    pub max: Time,
+}
+
+impl TimeSummary {
+    fn of(times: impl Iterator<Item = Time>) -> Option<Self> {
+        let times: Vec<_> = times.collect();
+        let count = times.len();
+        if count == 0 {
+            return None;
+        }
+        Some(Self {
+            count,
+            min: *times.iter().min()?,
+            mean: times.iter().sum::<Time>() / count as Time,
+            max: *times.iter().max()?,
+        })
+    }
+}
+
+impl fmt::Display for TimeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ops, min {}, mean {}, max {}",
+            self.count, self.min, self.mean, self.max
+        )
+    }
+}
+
+/// Per-op statistics of a run of the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyReport {
+    /// the virtual time at the end of the run
+    pub clock: Time,
+    /// the number of ops sent
+    pub ops: usize,
+    /// the time from sending an op to sending its proof, over the ops with a proof
+    pub time_to_proof: Option<TimeSummary>,
+    /// the time from sending an op to a supermajority delivering it, over the ops
+    /// delivered by a supermajority
+    pub time_to_supermajority: Option<TimeSummary>,
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ops by t={}", self.ops, self.clock)?;
+        if let Some(summary) = self.time_to_proof {
+            write!(f, "; time to proof: {}", summary)?;
+        }
+        if let Some(summary) = self.time_to_supermajority {
+            write!(f, "; time to supermajority delivery: {}", summary)?;
+        }
+        Ok(())
+    }
+}
+
+/// A seeded, configurable latency model.
+#[derive(Debug, Clone)]
+pub struct LatencyModel {
+    /// the seed of the RNG drawing delays
+    pub seed: u64,
+    /// latency of links without an override
+    pub default_latency: Latency,
+    /// per-link overrides, by (source, dest)
+    pub links: HashMap<(Actor, Actor), Latency>,
+    /// the timing of each op sent so far, by source and counter
+    pub ops: BTreeMap<(Actor, u64), OpTiming>,
+    rng: StdRng,
+}
+
+impl LatencyModel {
+    /// Create a latency model seeded with `seed`.
+    pub fn new(seed: u64, default_latency: Latency) -> Self {
+        info!(
+            "[NET] latency model seed: {} (set {} to replay)",
+            seed, SEED_ENV_VAR
+        );
+        Self {
+            seed,
+            default_latency,
+            links: Default::default(),
+            ops: Default::default(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Create a latency model seeded from `BRB_NET_SEED`, see `env_seed`.
+    pub fn from_env(default_latency: Latency) -> Self {
+        Self::new(env_seed(), default_latency)
+    }
+
+    /// Override the latency of the link from `source` to `dest`.
+    pub fn with_link(mut self, source: Actor, dest: Actor, latency: Latency) -> Self {
+        self.links.insert((source, dest), latency);
+        self
+    }
+
+    /// the latency of the link from `source` to `dest`.
+    pub fn link(&self, source: &Actor, dest: &Actor) -> Latency {
+        self.links
+            .get(&(*source, *dest))
+            .copied()
+            .unwrap_or(self.default_latency)
+    }
+
+    /// Draw the delay of a packet sent from `source` to `dest`.
+    pub fn delay(&mut self, source: &Actor, dest: &Actor) -> Time {
+        let latency = self.link(source, dest);
+        latency.sample(&mut self.rng)
+    }
+
+    /// Summarize the timings of the ops sent so far.
+    pub fn report(&self, clock: Time) -> LatencyReport {
+        LatencyReport {
+            clock,
+            ops: self.ops.len(),
+            time_to_proof: TimeSummary::of(self.ops.values().flat_map(OpTiming::time_to_proof)),
+            time_to_supermajority: TimeSummary::of(
+                self.ops.values().flat_map(OpTiming::time_to_supermajority),
+            ),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Network partitions for the simulated network.
+//!
+//! A partition splits actors into groups that can not reach each other. Packets sent
+//! across groups are either queued until the partition heals, or dropped.
+
+use std::collections::BTreeSet;
+
+use super::Actor;
+
+/// What happens to packets sent across a partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossPartition {
+    /// hold the packets back and deliver them once the partition heals
+    Queue,
+    /// lose the packets
+    Drop,
+}
+
+/// Groups of actors that can not reach each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    /// actors can only reach actors in their own group, actors that are not in
+    /// any group can reach everyone
+    pub groups: Vec<BTreeSet<Actor>>,
+    /// what happens to packets sent across groups
+    pub policy: CrossPartition,
+    /// the partition heals once this many packets have been delivered by the Net,
+    /// this is checked as packets are run through `Net::run_packets_to_completion`
+    pub heal_at: Option<u64>,
+}
+
+impl Partition {
+    /// Create a partition lasting until it's explicitly healed.
+    pub fn new(groups: Vec<BTreeSet<Actor>>, policy: CrossPartition) -> Self {
+        Self {
+            groups,
+            policy,
+            heal_at: None,
+        }
+    }
+
+    /// Heal the partition once `step` packets have been delivered by the Net.
+    pub fn heal_at(mut self, step: u64) -> Self {
+        self.heal_at = Some(step);
+        self
+    }
+
+    /// true if packets from `source` reach `dest`.
+    pub fn can_reach(&self, source: &Actor, dest: &Actor) -> bool {
+        let group_of = |actor| self.groups.iter().position(|g| g.contains(actor));
+        match (group_of(source), group_of(dest)) {
+            (Some(source_group), Some(dest_group)) => source_group == dest_group,
+            _ => true,
+        }
+    }
+
+    /// true if the partition should heal at this step.
+    pub fn is_healed(&self, step: u64) -> bool {
+        self.heal_at.map(|heal_at| step >= heal_at).unwrap_or(false)
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Whole BRB scenarios for the simulated network, and proptest strategies generating them.
+//!
+//! A Scenario is a group size, a sequence of events (rounds of concurrent ops, joins and
+//! leaves), the picks of the scheduler and the faults injected into packets. With the
+//! `proptest` feature, scenarios can be generated: picks shrink towards delivering packets
+//! in order and faults shrink towards delivering every packet, so a failing scenario
+//! shrinks down to a minimal packet trace.
+//!
+//! `Scenario::run` checks the BRB properties as packets are delivered, the first one
+//! violated is left in `Net::violation` and the packet trace in `Net::trace`.
+//! `Scenario::setup` only builds the network a scenario starts from, for tests that
+//! drive the packets themselves.
+
+use std::collections::BTreeSet;
+#[cfg(feature = "proptest")]
+use std::fmt::Debug;
+
+use brb_membership::{Generation, Reconfig};
+use crdts::VClock;
+#[cfg(feature = "proptest")]
+use proptest::{collection::vec, prelude::*};
+
+use super::byzantine::{NetError, NetResult};
+#[cfg(feature = "proptest")]
+use super::faults::DEFAULT_REORDER_WINDOW;
+use super::{Actor, Fault, FaultModel, Net, ReplayScheduler, BRBDT};
+
+/// The default largest group generated.
+pub const DEFAULT_MAX_PROCS: usize = 7;
+
+/// The default largest number of events generated.
+pub const DEFAULT_MAX_EVENTS: usize = 8;
+
+/// The default largest number of scheduler picks and faults generated.
+pub const DEFAULT_MAX_PACKETS: usize = 64;
+
+/// An event in a scenario.
+///
+/// Procs are given as indexes into the honest members at the time of the event, wrapping
+/// around, so events stay meaningful as scenarios shrink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<Op> {
+    /// members execute ops concurrently, a member executes at most one op per round
+    Ops(Vec<(usize, Op)>),
+    /// a new proc joins the group, sponsored by a member
+    Join {
+        /// the member proposing the new proc
+        sponsor: usize,
+    },
+    /// a member is voted out of the group
+    Leave {
+        /// the member proposing the removal
+        voter: usize,
+        /// the member to remove
+        proc: usize,
+    },
+}
+
+/// A BRB scenario for the simulated network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scenario<Op> {
+    /// the number of procs in the initial group
+    pub n_procs: usize,
+    /// the events, each is run to completion before the next
+    pub events: Vec<Event<Op>>,
+    /// the picks of the scheduler, packets are delivered in order once they run out
+    pub schedule: Vec<usize>,
+    /// the faults injected into the first packets, in order
+    pub faults: Vec<Fault>,
+}
+
+/// Bounds on the scenarios generated.
+#[cfg(feature = "proptest")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScenarioBounds {
+    /// the largest initial group
+    pub max_procs: usize,
+    /// the most events
+    pub max_events: usize,
+    /// the most scheduler picks and the most faults
+    pub max_packets: usize,
+    /// if false, no joins or leaves are generated
+    pub membership: bool,
+    /// if false, no packets are dropped
+    pub drops: bool,
+}
+
+#[cfg(feature = "proptest")]
+impl Default for ScenarioBounds {
+    fn default() -> Self {
+        Self {
+            max_procs: DEFAULT_MAX_PROCS,
+            max_events: DEFAULT_MAX_EVENTS,
+            max_packets: DEFAULT_MAX_PACKETS,
+            membership: true,
+            drops: true,
+        }
+    }
+}
+
+/// Generates a fault, shrinking towards delivering the packet.
+#[cfg(feature = "proptest")]
+pub fn fault(drops: bool) -> impl Strategy<Value = Fault> {
+    prop_oneof![
+        8 => Just(Fault::Deliver),
+        if drops { 1 } else { 0 } => Just(Fault::Drop),
+        1 => Just(Fault::Duplicate),
+        1 => (1..=DEFAULT_REORDER_WINDOW).prop_map(Fault::Reorder),
+    ]
+}
+
+/// Generates an event with ops drawn from `op`, shrinking towards a round of ops.
+#[cfg(feature = "proptest")]
+pub fn event<S>(bounds: ScenarioBounds, op: S) -> impl Strategy<Value = Event<S::Value>>
+where
+    S: Strategy + Clone,
+    S::Value: Clone + Debug,
+{
+    let member = 0..bounds.max_procs + bounds.max_events;
+    let membership = if bounds.membership { 1 } else { 0 };
+    prop_oneof![
+        4 => vec((member.clone(), op), 1..=bounds.max_procs).prop_map(Event::Ops),
+        membership => member.clone().prop_map(|sponsor| Event::Join { sponsor }),
+        membership => (member.clone(), member).prop_map(|(voter, proc)| Event::Leave { voter, proc }),
+    ]
+}
+
+/// Generates a scenario with ops drawn from `op`.
+#[cfg(feature = "proptest")]
+pub fn scenario<S>(bounds: ScenarioBounds, op: S) -> impl Strategy<Value = Scenario<S::Value>>
+where
+    S: Strategy + Clone,
+    S::Value: Clone + Debug,
+{
+    (
+        1..=bounds.max_procs,
+        vec(event(bounds, op), 0..=bounds.max_events),
+        vec(0..bounds.max_packets, 0..=bounds.max_packets),
+        vec(fault(bounds.drops), 0..=bounds.max_packets),
+    )
+        .prop_map(|(n_procs, events, schedule, faults)| Scenario {
+            n_procs,
+            events,
+            schedule,
+            faults,
+        })
+}
+
+/// An op or reconfig asked for by a scenario that its proc refused.
+#[derive(Debug)]
+pub struct Refusal<DT: BRBDT> {
+    /// the index of the event in the scenario
+    pub event: usize,
+    /// the proc asked to execute the op or reconfig
+    pub proc: Actor,
+    /// the error the proc refused it with
+    pub error: NetError<DT>,
+}
+
+/// The network a scenario ran on, and the ops and reconfigs its procs refused.
+#[derive(Debug)]
+pub struct Outcome<DT: BRBDT> {
+    /// the network after the scenario ran
+    pub net: Net<DT>,
+    /// the ops and reconfigs refused, in the order the scenario asked for them
+    pub refusals: Vec<Refusal<DT>>,
+}
+
+/// The most anti-entropy rounds run at the end of a scenario.
+pub const MAX_ANTI_ENTROPY_ROUNDS: usize = 8;
+
+impl<Op: Clone> Scenario<Op> {
+    /// A scenario of `n_procs` genesis members and nothing else.
+    pub fn genesis(n_procs: usize) -> Self {
+        Self {
+            n_procs,
+            events: vec![],
+            schedule: vec![],
+            faults: vec![],
+        }
+    }
+
+    /// Build the network the scenario starts from: a fresh proc for each genesis member
+    /// and each join, returned in key order. The first `n_procs` are the genesis members.
+    ///
+    /// Roles are given to actors in key order, so runs of the same scenario only differ
+    /// by the identity of the actors.
+    pub fn setup<DT: BRBDT<Op = Op>>(&self) -> (Net<DT>, Vec<Actor>) {
+        let mut net = Net::new();
+        let n_joins = self
+            .events
+            .iter()
+            .filter(|e| matches!(e, Event::Join { .. }))
+            .count();
+        let mut actors: Vec<_> = (0..self.n_procs + n_joins)
+            .map(|_| net.initialize_proc())
+            .collect();
+        actors.sort();
+        net.procs.sort_by_key(|proc| proc.actor());
+        for proc in net.procs.iter_mut() {
+            for actor in actors[..self.n_procs].iter() {
+                proc.force_join(*actor);
+            }
+        }
+        (net, actors)
+    }
+
+    /// Run the scenario against fresh procs checking the BRB properties, then recover
+    /// dropped packets through anti-entropy and check that all honest members agree.
+    ///
+    /// Ops and reconfigs refused by their proc, e.g. while it waits on an earlier msg,
+    /// are skipped and returned in the outcome.
+    pub fn run<DT: BRBDT<Op = Op>>(&self) -> Outcome<DT> {
+        let (mut net, actors) = self.setup();
+        net.invariants = Some(Default::default());
+        net.scheduler = Box::new(ReplayScheduler::new(self.schedule.clone()));
+        net.faults = Some(FaultModel::scripted(self.faults.iter().copied()));
+        // packets may be dropped until anti-entropy runs at the end.
+        if let Some(invariants) = net.invariants.as_mut() {
+            invariants.check_totality = false;
+        }
+        let mut joining = actors[self.n_procs..].iter();
+        let mut refusals = vec![];
+        net.record_trace();
+
+        for (index, event) in self.events.iter().enumerate() {
+            let members: Vec<_> = net.members().into_iter().collect();
+            if net.violation.is_some() || members.is_empty() {
+                break;
+            }
+            let member = |i: usize| members[i % members.len()];
+            let mut or_refused = |proc: Actor, result: NetResult<DT>| match result {
+                Ok(packets) => packets,
+                Err(error) => {
+                    refusals.push(Refusal {
+                        event: index,
+                        proc,
+                        error,
+                    });
+                    vec![]
+                }
+            };
+
+            let packets = match event {
+                Event::Ops(ops) => {
+                    let mut sources = BTreeSet::new();
+                    let mut packets = vec![];
+                    for (source, op) in ops.iter() {
+                        let source = member(*source);
+                        if sources.insert(source) {
+                            let result = net.exec_op(&source, op.clone());
+                            packets.extend(or_refused(source, result));
+                        }
+                    }
+                    packets
+                }
+                Event::Join { sponsor } => {
+                    let sponsor = member(*sponsor);
+                    match joining.next() {
+                        Some(actor) => {
+                            let result = net.propose(&sponsor, Reconfig::Join(*actor));
+                            let packets = or_refused(sponsor, result);
+                            net.run_packets_to_completion(packets);
+                            // the new proc bootstraps from its sponsor.
+                            match net.proc(actor) {
+                                Some(proc) => {
+                                    let result = proc.anti_entropy(sponsor).map(|p| vec![p]);
+                                    or_refused(*actor, result)
+                                }
+                                None => vec![],
+                            }
+                        }
+                        None => vec![],
+                    }
+                }
+                Event::Leave { voter, proc } => {
+                    let result = net.propose(&member(*voter), Reconfig::Leave(member(*proc)));
+                    or_refused(member(*voter), result)
+                }
+            };
+            net.run_packets_to_completion(packets);
+        }
+
+        // Replaying buffered packets during anti-entropy may deliver msgs that procs
+        // which already caught up then miss, so it's repeated until it makes no progress.
+        net.faults = None;
+        for _ in 0..MAX_ANTI_ENTROPY_ROUNDS {
+            let progress = Self::progress(&net);
+            net.anti_entropy();
+            if Self::progress(&net) == progress {
+                break;
+            }
+        }
+        if let Some(invariants) = net.invariants.as_mut() {
+            invariants.check_totality = true;
+        }
+        net.anti_entropy();
+        Outcome { net, refusals }
+    }
+
+    /// The generation and delivered clock of every proc.
+    fn progress<DT: BRBDT<Op = Op>>(net: &Net<DT>) -> Vec<(Generation, VClock<Actor>)> {
+        net.procs
+            .iter()
+            .map(|proc| (proc.membership.gen, proc.delivered.clone()))
+            .collect()
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Schedulers deciding the order packets are delivered in by the simulated network,
+//! and an explorer enumerating delivery orders.
+//!
+//! A scheduler picks which pending packet `Net::run_packets_to_completion` delivers
+//! next. Every pick is recorded in `Net::schedule`, and a ReplayScheduler given the
+//! picks of a run makes the same picks again.
+//!
+//! `explore` runs a network built by a setup function once per delivery order, up to a
+//! bound, checking invariants after every step. Picks are positions in the pending
+//! packets, so the setup must queue the same packets in the same order on every call.
+//! Keys are random, so a setup should assign roles to actors in key order, then runs
+//! differ only by the identity of the actors.
+
+use std::fmt::Debug;
+
+use log::info;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::faults::{env_seed, Seeds, SEED_ENV_VAR};
+use super::{Net, Packet, BRBDT};
+
+/// The default number of steps `explore` enumerates the picks of.
+pub const DEFAULT_EXPLORE_DEPTH: usize = 8;
+
+/// The default number of delivery orders `explore` runs.
+pub const DEFAULT_EXPLORE_SCHEDULES: usize = 1000;
+
+/// Picks the next packet to deliver.
+pub trait Scheduler: Debug {
+    /// the index of the next packet to deliver out of `pending` packets, `pending` is never 0.
+    fn pick(&mut self, pending: usize) -> usize;
+
+    /// the seed of the scheduler's RNG, if its picks are random.
+    fn seed(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A pick made by a scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pick {
+    /// the index of the packet that was picked
+    pub index: usize,
+    /// the number of packets that were pending
+    pub pending: usize,
+}
+
+/// Delivers packets in the order they were sent.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Fifo;
+
+impl Scheduler for Fifo {
+    fn pick(&mut self, _pending: usize) -> usize {
+        0
+    }
+}
+
+/// Delivers pending packets in a random order drawn from a seeded RNG.
+#[derive(Debug, Clone)]
+pub struct RandomScheduler {
+    /// the seed of the RNG making picks
+    pub seed: u64,
+    rng: StdRng,
+}
+
+impl RandomScheduler {
+    /// Create a scheduler seeded with `seed`.
+    pub fn new(seed: u64) -> Self {
+        info!(
+            "[NET] scheduler seed: {} (set {} to replay)",
+            seed, SEED_ENV_VAR
+        );
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Create a scheduler seeded from `BRB_NET_SEED`, see `env_seed`.
+    pub fn from_env() -> Self {
+        Self::new(env_seed())
+    }
+}
+
+impl Scheduler for RandomScheduler {
+    fn pick(&mut self, pending: usize) -> usize {
+        self.rng.gen_range(0..pending)
+    }
+
+    fn seed(&self) -> Option<u64> {
+        Some(self.seed)
+    }
+}
+
+/// Makes the given picks, then delivers packets in the order they were sent.
+#[derive(Debug, Default, Clone)]
+pub struct ReplayScheduler {
+    /// the picks to make, out of range picks are clamped to the last pending packet
+    pub picks: Vec<usize>,
+    next: usize,
+}
+
+impl ReplayScheduler {
+    /// Create a scheduler making the given picks.
+    pub fn new(picks: Vec<usize>) -> Self {
+        Self { picks, next: 0 }
+    }
+}
+
+impl Scheduler for ReplayScheduler {
+    fn pick(&mut self, pending: usize) -> usize {
+        let index = self.picks.get(self.next).copied().unwrap_or(0);
+        self.next += 1;
+        index.min(pending - 1)
+    }
+}
+
+/// An invariant that failed while running packets through the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// the number of packets delivered by the network when the invariant failed
+    pub step: u64,
+    /// the picks leading to the failure, replay them with a ReplayScheduler
+    pub schedule: Vec<usize>,
+    /// the reason given by the invariant
+    pub reason: String,
+    /// the seeds of the network's randomized components
+    pub seeds: Seeds,
+}
+
+/// Bounds on the delivery orders enumerated by `explore`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExploreBounds {
+    /// every pick is enumerated for this many steps, later packets are delivered in order
+    pub depth: usize,
+    /// the most delivery orders to run
+    pub schedules: usize,
+}
+
+impl Default for ExploreBounds {
+    fn default() -> Self {
+        Self {
+            depth: DEFAULT_EXPLORE_DEPTH,
+            schedules: DEFAULT_EXPLORE_SCHEDULES,
+        }
+    }
+}
+
+/// Run the network built by `setup` once per delivery order within `bounds`, calling
+/// `check` after every step.
+///
+/// Returns the number of delivery orders that were run, or the first violation found.
+pub fn explore<DT, S, C>(
+    bounds: ExploreBounds,
+    mut setup: S,
+    mut check: C,
+) -> Result<usize, Violation>
+where
+    DT: BRBDT,
+    S: FnMut() -> (Net<DT>, Vec<Packet<DT::Op>>),
+    C: FnMut(&Net<DT>, &[Packet<DT::Op>]) -> Result<(), String>,
+{
+    let mut prefix = vec![];
+    let mut runs = 0;
+    while runs < bounds.schedules {
+        let (mut net, packets) = setup();
+        net.scheduler = Box::new(ReplayScheduler::new(prefix));
+        net.run_packets_checked(packets, &mut check)?;
+        runs += 1;
+
+        // Backtrack to the deepest pick with a sibling left to try.
+        let picks = &net.schedule[..net.schedule.len().min(bounds.depth)];
+        match picks.iter().rposition(|p| p.index + 1 < p.pending) {
+            Some(step) => {
+                prefix = picks[..step].iter().map(|p| p.index).collect();
+                prefix.push(picks[step].index + 1);
+            }
+            None => break,
+        }
+    }
+    Ok(runs)
+}
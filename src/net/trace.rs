@@ -0,0 +1,301 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Recording and replaying packet traces of the simulated network.
+//!
+//! Once `Net::record_trace` is called, the network records its fault model and the
+//! keys, peers and config of its procs, then every op executed, every reconfig proposed
+//! and every packet delivered through the Net, along with the packets each of them sent
+//! or the error it was refused with.
+//!
+//! A Trace saved to a file replays against fresh procs given the same keys and config.
+//! Signatures are deterministic, so an honest proc sends the same bytes again, and the
+//! first step that sends different packets or is refused differently is reported as a
+//! Divergence.
+//!
+//! Recording should start once procs are bootstrapped and before any packet is sent.
+//! Byzantine behaviours are only recorded by name, steps of byzantine procs diverge on
+//! replay.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use brb_membership::{Generation, Reconfig};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use ed25519::Keypair;
+
+use super::byzantine::NetResult;
+use super::{Actor, Fault, FaultModel, LinkFaults, Net, Packet, SigningActor, State, BRBDT};
+use crate::{RateLimits, ReputationConfig};
+
+/// The config of a proc when recording started.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcConfig {
+    /// see `DeterministicBRB::reorder_buffer_limit`
+    pub reorder_buffer_limit: usize,
+    /// see `DeterministicBRB::reorder_buffer_total_limit`
+    pub reorder_buffer_total_limit: usize,
+    /// see `DeterministicBRB::generation_lookahead`
+    pub generation_lookahead: Generation,
+    /// see `DeterministicBRB::auto_catch_up`
+    pub auto_catch_up: bool,
+    /// see `DeterministicBRB::catch_up_cooldown`
+    pub catch_up_cooldown: u64,
+    /// the rate limits of the proc
+    pub rate_limits: RateLimits,
+    /// the reputation config of the proc
+    pub reputation: ReputationConfig,
+}
+
+impl ProcConfig {
+    fn of<DT: BRBDT>(proc: &State<DT>) -> Self {
+        Self {
+            reorder_buffer_limit: proc.reorder_buffer_limit,
+            reorder_buffer_total_limit: proc.reorder_buffer_total_limit,
+            generation_lookahead: proc.generation_lookahead,
+            auto_catch_up: proc.auto_catch_up,
+            catch_up_cooldown: proc.catch_up_cooldown,
+            rate_limits: proc.rate_limiter.limits,
+            reputation: proc.reputation.config,
+        }
+    }
+
+    fn apply<DT: BRBDT>(&self, proc: &mut State<DT>) {
+        proc.reorder_buffer_limit = self.reorder_buffer_limit;
+        proc.reorder_buffer_total_limit = self.reorder_buffer_total_limit;
+        proc.generation_lookahead = self.generation_lookahead;
+        proc.auto_catch_up = self.auto_catch_up;
+        proc.catch_up_cooldown = self.catch_up_cooldown;
+        proc.rate_limiter.limits = self.rate_limits;
+        proc.reputation.config = self.reputation;
+    }
+}
+
+/// The keys, peers and config of a proc when recording started.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TracedProc {
+    /// the keypair of the proc
+    pub key: Keypair,
+    /// the peers of the proc
+    pub peers: BTreeSet<Actor>,
+    /// the config of the proc
+    pub config: ProcConfig,
+    /// the byzantine behaviour of the proc, by its Debug form, if it had one
+    pub byzantine: Option<String>,
+}
+
+/// The fault model of the network when recording started.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TracedFaults {
+    /// the seed of the fault model
+    pub seed: u64,
+    /// faults on links without an override
+    pub default_faults: LinkFaults,
+    /// per-link overrides, by (source, dest)
+    pub links: BTreeMap<(Actor, Actor), LinkFaults>,
+    /// the number of queued packets a reordered packet may be delayed behind
+    pub reorder_window: usize,
+    /// the faults scripted for the next packets
+    pub script: Vec<Fault>,
+}
+
+impl TracedFaults {
+    fn of(faults: &FaultModel) -> Self {
+        Self {
+            seed: faults.seed,
+            default_faults: faults.default_faults,
+            links: faults.links.iter().map(|(k, v)| (*k, *v)).collect(),
+            reorder_window: faults.reorder_window,
+            script: faults.script.iter().copied().collect(),
+        }
+    }
+
+    /// A fault model making the same decisions as the recorded one for the same packets.
+    pub fn model(&self) -> FaultModel {
+        let mut model = FaultModel::new(self.seed, self.default_faults);
+        model.links = self.links.iter().map(|(k, v)| (*k, *v)).collect();
+        model.reorder_window = self.reorder_window;
+        model.script = self.script.iter().copied().collect();
+        model
+    }
+}
+
+/// A step taken by the network, with the packets it sent.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Step<Op> {
+    /// a proc executed an op
+    ExecOp {
+        /// the proc executing the op
+        proc: Actor,
+        /// the op
+        op: Op,
+        /// the packets sent
+        sent: Vec<Packet<Op>>,
+        /// the error the proc refused the op with, if it did
+        refused: Option<String>,
+    },
+    /// a proc proposed a reconfig
+    Reconfig {
+        /// the proc proposing the reconfig
+        proc: Actor,
+        /// the reconfig
+        reconfig: Reconfig<Actor>,
+        /// the packets sent
+        sent: Vec<Packet<Op>>,
+        /// the error the proc refused the reconfig with, if it did
+        refused: Option<String>,
+    },
+    /// a packet was delivered
+    Deliver {
+        /// the packet
+        packet: Packet<Op>,
+        /// the packets sent in response
+        sent: Vec<Packet<Op>>,
+    },
+}
+
+impl<Op> Step<Op> {
+    /// the packets sent by this step.
+    pub fn sent(&self) -> &[Packet<Op>] {
+        match self {
+            Step::ExecOp { sent, .. }
+            | Step::Reconfig { sent, .. }
+            | Step::Deliver { sent, .. } => sent,
+        }
+    }
+
+    /// the error this step was refused with, if it was.
+    pub fn refused(&self) -> Option<&str> {
+        match self {
+            Step::ExecOp { refused, .. } | Step::Reconfig { refused, .. } => refused.as_deref(),
+            Step::Deliver { .. } => None,
+        }
+    }
+}
+
+/// The packets sent and the error refused with, for the result of an op or reconfig.
+pub(crate) fn outcome<DT: BRBDT>(result: &NetResult<DT>) -> (Vec<Packet<DT::Op>>, Option<String>) {
+    match result {
+        Ok(sent) => (sent.clone(), None),
+        Err(err) => (vec![], Some(err.to_string())),
+    }
+}
+
+/// A recorded run of the simulated network.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Trace<Op> {
+    /// the network's fault model, if it had one
+    pub faults: Option<TracedFaults>,
+    /// if true, procs replied to packets they rejected with a Rejected packet
+    pub send_rejections: bool,
+    /// the procs of the network when recording started
+    pub procs: Vec<TracedProc>,
+    /// the steps taken by the network, in order
+    pub steps: Vec<Step<Op>>,
+}
+
+/// The first step of a replay sending different packets, or refused differently, than
+/// was recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence<Op> {
+    /// the index of the step in the trace
+    pub step: usize,
+    /// the packets sent when the trace was recorded
+    pub expected: Vec<Packet<Op>>,
+    /// the packets sent on replay
+    pub actual: Vec<Packet<Op>>,
+    /// the error the step was refused with when the trace was recorded
+    pub expected_refusal: Option<String>,
+    /// the error the step was refused with on replay
+    pub actual_refusal: Option<String>,
+}
+
+impl<Op: Serialize + DeserializeOwned> Trace<Op> {
+    /// Write the trace to a file, encoded with bincode.
+    pub fn save(&self, path: impl AsRef<Path>) -> bincode::Result<()> {
+        let file = File::create(path)?;
+        bincode::serialize_into(BufWriter::new(file), self)
+    }
+
+    /// Read a trace written by `Trace::save`.
+    pub fn load(path: impl AsRef<Path>) -> bincode::Result<Self> {
+        let file = File::open(path)?;
+        bincode::deserialize_from(BufReader::new(file))
+    }
+}
+
+impl<Op: Clone + Eq> Trace<Op> {
+    pub(crate) fn record<DT: BRBDT<Op = Op>>(net: &Net<DT>) -> Self {
+        let procs = net
+            .procs
+            .iter()
+            .map(|proc| TracedProc {
+                key: copy_key(&proc.membership.id.0),
+                peers: proc.peers().unwrap_or_default(),
+                config: ProcConfig::of(proc),
+                byzantine: net
+                    .byzantine
+                    .get(&proc.actor())
+                    .map(|behaviour| format!("{:?}", behaviour)),
+            })
+            .collect();
+        Self {
+            faults: net.faults.as_ref().map(TracedFaults::of),
+            send_rejections: net.send_rejections,
+            procs,
+            steps: vec![],
+        }
+    }
+
+    /// Re-run the trace against fresh procs with the recorded keys, peers and config.
+    ///
+    /// Returns the replayed network, or the first step that sent different packets or
+    /// was refused differently.
+    pub fn replay<DT: BRBDT<Op = Op>>(&self) -> Result<Net<DT>, Divergence<Op>> {
+        let mut net = Net::new();
+        net.send_rejections = self.send_rejections;
+        for traced in self.procs.iter() {
+            let mut proc = State::with_signing_actor(SigningActor(copy_key(&traced.key)));
+            for peer in traced.peers.iter() {
+                proc.force_join(*peer);
+            }
+            traced.config.apply(&mut proc);
+            net.procs.push(proc);
+        }
+
+        for (i, step) in self.steps.iter().enumerate() {
+            let (actual, actual_refusal) = match step.clone() {
+                Step::ExecOp { proc, op, .. } => outcome::<DT>(&net.exec_op(&proc, op)),
+                Step::Reconfig { proc, reconfig, .. } => {
+                    outcome::<DT>(&net.propose(&proc, reconfig))
+                }
+                Step::Deliver { packet, .. } => (net.deliver_packet(packet), None),
+            };
+            if actual != step.sent() || actual_refusal.as_deref() != step.refused() {
+                return Err(Divergence {
+                    step: i,
+                    expected: step.sent().to_vec(),
+                    actual,
+                    expected_refusal: step.refused().map(String::from),
+                    actual_refusal,
+                });
+            }
+        }
+        Ok(net)
+    }
+}
+
+/// Keypairs are not Clone, they are copied through their encoding.
+fn copy_key(key: &Keypair) -> Keypair {
+    Keypair::from_bytes(&key.to_bytes()).expect("Failed to copy keypair")
+}
@@ -15,6 +15,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::deterministic_brb;
 use crate::{Actor, Sig};
+use brb_membership::Generation;
+use crdts::Dot;
 
 /// Represents a logical message packet with a BRB specific payload.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -44,4 +46,49 @@ pub enum Payload<A: Actor<S>, S: Sig, DataTypeOp> {
     // Box to avoid https://rust-lang.github.io/rust-clippy/master/index.html#large_enum_variant
     /// Represents a brb_membership Vote
     Membership(Box<brb_membership::Vote<A, S>>),
+    /// Lets the source of a packet know that we rejected it and why
+    Rejected {
+        /// why the packet was rejected
+        reason: RejectionReason,
+        /// dot of the rejected msg, if the packet carried one
+        dot: Option<Dot<A>>,
+    },
+}
+
+/// A compact, machine-readable reason for rejecting a packet.
+///
+/// Reasons are from the point of view of the peer that rejected the packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RejectionReason {
+    /// The msg is older than what we expected from its source
+    Behind,
+    /// The msg is ahead of what we've seen from its source, we need to catch up
+    Ahead,
+    /// The source already has a msg pending delivery
+    PendingMsg,
+    /// The msg is not from our generation
+    WrongGeneration {
+        /// our generation
+        gen: Generation,
+    },
+    /// The source is not a voting member
+    NotAMember,
+    /// The data type failed to validate the op
+    DataTypeRejected,
+    /// The proof of agreement is not valid
+    InvalidProof,
+    /// A signature failed verification
+    InvalidSignature,
+    /// The msg conflicts with one we've already signed
+    Conflict,
+    /// We were not expecting this packet, e.g. a response to a request we did not make
+    NotExpected,
+    /// The packet is not well formed
+    Malformed,
+    /// The membership vote was refused
+    MembershipRejected,
+    /// The source has exceeded its rate limit
+    RateLimited,
+    /// The source has misbehaved and we are dropping its packets
+    Quarantined,
 }
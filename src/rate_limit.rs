@@ -0,0 +1,140 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Per-peer rate limiting of the packets that are expensive for us to handle.
+//!
+//! Any member may send us an AntiEntropy request with an empty clock and have us sign
+//! and send our entire history, or flood us with validation requests and membership votes.
+//! RateLimiter keeps a token bucket per peer and kind of packet to bound this work.
+//!
+//! Buckets are refilled on the logical clock of
+//! [`packets_handled`](crate::deterministic_brb::DeterministicBRB::packets_handled).
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The kinds of packets that may be rate limited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum LimitedPayload {
+    /// Payload::AntiEntropy
+    AntiEntropy,
+    /// Payload::BRB(Op::RequestValidation)
+    RequestValidation,
+    /// Payload::Membership
+    Membership,
+}
+
+/// Configuration of a token bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// The maximum number of tokens in the bucket, i.e. the largest allowed burst
+    pub capacity: u64,
+    /// A token is regained every `refill_interval` packets handled, 0 disables refilling
+    pub refill_interval: u64,
+}
+
+/// Rate limits for each kind of packet, None means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimits {
+    /// limit on AntiEntropy requests per peer
+    pub anti_entropy: Option<RateLimit>,
+    /// limit on RequestValidation ops per peer
+    pub request_validation: Option<RateLimit>,
+    /// limit on membership votes per peer
+    pub membership: Option<RateLimit>,
+}
+
+impl RateLimits {
+    /// returns the limit for a kind of packet, if any
+    pub fn get(&self, kind: LimitedPayload) -> Option<RateLimit> {
+        match kind {
+            LimitedPayload::AntiEntropy => self.anti_entropy,
+            LimitedPayload::RequestValidation => self.request_validation,
+            LimitedPayload::Membership => self.membership,
+        }
+    }
+}
+
+/// A token bucket, refilled according to a RateLimit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenBucket {
+    /// tokens currently in the bucket
+    pub tokens: u64,
+    /// when we last refilled the bucket, in packets handled
+    pub last_refill: u64,
+}
+
+impl TokenBucket {
+    /// returns a full bucket
+    pub fn new(limit: RateLimit, now: u64) -> Self {
+        Self {
+            tokens: limit.capacity,
+            last_refill: now,
+        }
+    }
+
+    /// Takes a token from the bucket, refilling it first.
+    /// Returns false if the bucket is empty.
+    pub fn try_take(&mut self, limit: RateLimit, now: u64) -> bool {
+        // checked_div gives None when refilling is disabled
+        if let Some(refills) = now
+            .saturating_sub(self.last_refill)
+            .checked_div(limit.refill_interval)
+        {
+            self.tokens = limit.capacity.min(self.tokens.saturating_add(refills));
+            self.last_refill += refills * limit.refill_interval;
+        }
+
+        if self.tokens > 0 {
+            self.tokens -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Keeps a token bucket for each peer and kind of limited packet.
+#[derive(Debug, Clone)]
+pub struct RateLimiter<A: Ord> {
+    /// the configured limits
+    pub limits: RateLimits,
+    /// token buckets, by peer and kind of packet
+    pub buckets: BTreeMap<(A, LimitedPayload), TokenBucket>,
+}
+
+impl<A: Ord> Default for RateLimiter<A> {
+    /// returns a RateLimiter without any limits
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<A: Ord> RateLimiter<A> {
+    /// returns a new RateLimiter enforcing the given limits
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            buckets: Default::default(),
+        }
+    }
+
+    /// true if peer is allowed to send us another packet of this kind at logical time `now`.
+    pub fn allow(&mut self, peer: A, kind: LimitedPayload, now: u64) -> bool {
+        match self.limits.get(kind) {
+            Some(limit) => self
+                .buckets
+                .entry((peer, kind))
+                .or_insert_with(|| TokenBucket::new(limit, now))
+                .try_take(limit, now),
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Peer reputation, scored from the packets we reject.
+//!
+//! Each rejected packet adds a penalty to the offending peer, weighted by the severity
+//! of the error, unless we rejected it because we are behind the peer. Penalties decay over time so that honest peers that were briefly out of
+//! sync recover. Peers whose penalty crosses the configured thresholds have their packets
+//! dropped (quarantine) and may be proposed for removal from the voting group.
+//!
+//! Penalties decay on the logical clock of
+//! [`packets_handled`](crate::deterministic_brb::DeterministicBRB::packets_handled).
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Severity;
+
+/// Default penalty for a transient error.
+pub const DEFAULT_TRANSIENT_PENALTY: u64 = 1;
+
+/// Default penalty for a byzantine error.
+pub const DEFAULT_BYZANTINE_PENALTY: u64 = 20;
+
+/// Default number of packets handled before one penalty point is forgiven.
+pub const DEFAULT_DECAY_INTERVAL: u64 = 4;
+
+/// Configures how peers are scored and what happens once they misbehave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReputationConfig {
+    /// penalty for errors of Severity::Transient
+    pub transient_penalty: u64,
+    /// penalty for errors of Severity::Byzantine
+    pub byzantine_penalty: u64,
+    /// one penalty point is forgiven every `decay_interval` packets handled, 0 disables decay
+    pub decay_interval: u64,
+    /// packets from peers with at least this penalty are dropped, None disables quarantine
+    pub quarantine_threshold: Option<u64>,
+    /// peers with at least this penalty are proposed for removal, None disables removal
+    pub removal_threshold: Option<u64>,
+}
+
+impl Default for ReputationConfig {
+    /// returns a config that scores peers but never quarantines or removes them
+    fn default() -> Self {
+        Self {
+            transient_penalty: DEFAULT_TRANSIENT_PENALTY,
+            byzantine_penalty: DEFAULT_BYZANTINE_PENALTY,
+            decay_interval: DEFAULT_DECAY_INTERVAL,
+            quarantine_threshold: None,
+            removal_threshold: None,
+        }
+    }
+}
+
+/// The penalty accumulated by a peer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerScore {
+    /// accumulated penalty, as of `last_update`
+    pub penalty: u64,
+    /// when the penalty was last updated, in packets handled
+    pub last_update: u64,
+}
+
+impl PeerScore {
+    /// returns the penalty after decaying it up to logical time `now`
+    pub fn decayed_penalty(&self, decay_interval: u64, now: u64) -> u64 {
+        let forgiven = now
+            .saturating_sub(self.last_update)
+            .checked_div(decay_interval)
+            .unwrap_or(0);
+        self.penalty.saturating_sub(forgiven)
+    }
+}
+
+/// Tracks the reputation of each peer.
+#[derive(Debug, Clone)]
+pub struct Reputation<A: Ord> {
+    /// how peers are scored
+    pub config: ReputationConfig,
+    /// scores, by peer
+    pub scores: BTreeMap<A, PeerScore>,
+}
+
+impl<A: Ord> Default for Reputation<A> {
+    /// returns a Reputation with the default config
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<A: Ord> Reputation<A> {
+    /// returns a new Reputation with the given config
+    pub fn new(config: ReputationConfig) -> Self {
+        Self {
+            config,
+            scores: Default::default(),
+        }
+    }
+
+    /// Penalizes peer for an error of the given severity at logical time `now`.
+    /// Returns the peer's new penalty.
+    pub fn penalize(&mut self, peer: A, severity: Severity, now: u64) -> u64 {
+        let penalty = match severity {
+            Severity::Local => 0,
+            Severity::Transient => self.config.transient_penalty,
+            Severity::Byzantine => self.config.byzantine_penalty,
+        };
+
+        let decay_interval = self.config.decay_interval;
+        let score = self.scores.entry(peer).or_default();
+        let decayed = score.decayed_penalty(decay_interval, now);
+        // Only move last_update past the points forgiven so far, otherwise a peer
+        // penalized more often than every decay_interval packets is never forgiven.
+        score.last_update = match (decayed, decay_interval) {
+            (0, _) | (_, 0) => now,
+            _ => score.last_update + (score.penalty - decayed) * decay_interval,
+        };
+        score.penalty = decayed.saturating_add(penalty);
+        score.penalty
+    }
+
+    /// returns the penalty of peer at logical time `now`
+    pub fn penalty(&self, peer: &A, now: u64) -> u64 {
+        self.scores
+            .get(peer)
+            .map(|score| score.decayed_penalty(self.config.decay_interval, now))
+            .unwrap_or(0)
+    }
+
+    /// true if we should drop packets from peer
+    pub fn is_quarantined(&self, peer: &A, now: u64) -> bool {
+        match self.config.quarantine_threshold {
+            Some(threshold) => self.penalty(peer, now) >= threshold,
+            None => false,
+        }
+    }
+
+    /// returns the peers we should propose to remove from the voting group
+    pub fn peers_to_remove(&self, now: u64) -> Vec<&A> {
+        match self.config.removal_threshold {
+            Some(threshold) => self
+                .scores
+                .keys()
+                .filter(|peer| self.penalty(peer, now) >= threshold)
+                .collect(),
+            None => vec![],
+        }
+    }
+}
@@ -0,0 +1,312 @@
+mod common;
+
+use brb::{
+    acl::AclError,
+    kv_store::KvError,
+    net::{explore, Actor, ExploreBounds, Net, Packet},
+    AccessControlled, AclOp, BRBDataType, BRBKvStore, DeliveryOutcome, KvOp, OpClassifier,
+};
+use serde::Serialize;
+
+use common::{bootstrap_net, run_packets_holding_back};
+
+type KvStore = BRBKvStore<Actor, String, String>;
+type AclKvStore = AccessControlled<Actor, KvStore, KvOpClassifier>;
+type AclKvOp = AclOp<Actor, KvOpClass, KvOp<Actor, String, String>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+enum KvOpClass {
+    Put,
+    Delete,
+}
+
+#[derive(Debug)]
+struct KvOpClassifier;
+
+impl OpClassifier<KvOp<Actor, String, String>> for KvOpClassifier {
+    type Class = KvOpClass;
+
+    fn classify(op: &KvOp<Actor, String, String>) -> Self::Class {
+        match op {
+            KvOp::Put { .. } => KvOpClass::Put,
+            KvOp::Delete { .. } => KvOpClass::Delete,
+        }
+    }
+}
+
+/// Bootstraps a network where the first actor is the initial admin.
+fn bootstrap_acl_net(n_procs: usize) -> (Net<AclKvStore>, Vec<Actor>) {
+    let (mut net, actors) = bootstrap_net::<AclKvStore>(n_procs);
+    for proc in net.procs.iter_mut() {
+        proc.dt.force_admin(actors[0]);
+    }
+    (net, actors)
+}
+
+/// Builds an op with the data type of the actor's replica.
+fn build(
+    net: &Net<AclKvStore>,
+    actor: &Actor,
+    build: impl FnOnce(&AclKvStore) -> AclKvOp,
+) -> Result<AclKvOp, &'static str> {
+    Ok(build(&net.proc(actor).ok_or("No proc for actor")?.dt))
+}
+
+fn exec(net: &mut Net<AclKvStore>, actor: &Actor, op: AclKvOp) -> Result<(), &'static str> {
+    let packets = net
+        .proc_mut(actor)
+        .ok_or("No proc for actor")?
+        .exec_op(op)
+        .map_err(|_| "Failed to generate op")?;
+    net.run_packets_to_completion(packets);
+    Ok(())
+}
+
+#[test]
+fn test_acl_is_enforced_on_inner_ops() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_acl_net(3);
+    let admin = actors[0];
+    let actor_b = actors[1];
+    let actor_c = actors[2];
+
+    let grant = build(&net, &admin, |dt| dt.grant(actor_b, KvOpClass::Put))?;
+    exec(&mut net, &admin, grant)?;
+
+    let proc_b = net.proc(&actor_b).ok_or("No proc for actor_b")?;
+    let op = AclOp::Op(proc_b.dt.dt.put("motd".to_string(), "hi".to_string()));
+    exec(&mut net, &actor_b, op)?;
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+    for proc in net.procs.iter() {
+        assert!(proc.dt.acl.is_permitted(&actor_b, &KvOpClass::Put));
+        assert_eq!(proc.dt.dt.get(&"motd".to_string()), Some(&"hi".to_string()));
+    }
+
+    // actor_b was only granted puts
+    let proc_b = net.proc(&actor_b).ok_or("No proc for actor_b")?;
+    let op = AclOp::Op(proc_b.dt.dt.delete("motd".to_string()));
+    assert!(matches!(
+        proc_b.dt.validate(&actor_b, &op),
+        Err(AclError::NotPermitted)
+    ));
+
+    // actor_c was granted nothing
+    let proc_c = net.proc(&actor_c).ok_or("No proc for actor_c")?;
+    let op = AclOp::Op(proc_c.dt.dt.put("other".to_string(), "x".to_string()));
+    assert!(matches!(
+        proc_c.dt.validate(&actor_c, &op),
+        Err(AclError::NotPermitted)
+    ));
+
+    // the inner data type still validates the ops that pass the ACL
+    let op = AclOp::Op(proc_c.dt.dt.put("motd".to_string(), "bye".to_string()));
+    assert!(matches!(
+        proc_c.dt.validate(&admin, &op),
+        Err(AclError::DataType(KvError::ActorIsNotSource))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_acl_is_changed_through_brb() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_acl_net(3);
+    let admin = actors[0];
+    let actor_b = actors[1];
+
+    // only admins may change the ACL
+    let grant = build(&net, &actor_b, |dt| dt.grant(actor_b, KvOpClass::Delete))?;
+    let proc_b = net.proc_mut(&actor_b).ok_or("No proc for actor_b")?;
+    assert!(proc_b.exec_op(grant.clone()).is_err());
+
+    let add_admin = build(&net, &admin, |dt| dt.add_admin(actor_b))?;
+    exec(&mut net, &admin, add_admin)?;
+    exec(&mut net, &actor_b, grant)?;
+    for proc in net.procs.iter() {
+        assert!(proc.dt.acl.is_admin(&actor_b));
+        assert!(proc.dt.acl.is_granted(&actor_b, &KvOpClass::Delete));
+    }
+
+    let remove_admin = build(&net, &actor_b, |dt| dt.remove_admin(admin))?;
+    exec(&mut net, &actor_b, remove_admin)?;
+    let revoke = build(&net, &actor_b, |dt| dt.revoke(actor_b, KvOpClass::Delete))?;
+    exec(&mut net, &actor_b, revoke)?;
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+    for proc in net.procs.iter() {
+        assert!(!proc.dt.acl.is_admin(&admin));
+        assert!(!proc.dt.acl.is_granted(&actor_b, &KvOpClass::Delete));
+        assert_eq!(proc.dt.acl.grant_version(&actor_b, &KvOpClass::Delete), 2);
+    }
+
+    // the last admin can not remove themselves
+    let proc_b = net.proc(&actor_b).ok_or("No proc for actor_b")?;
+    assert!(matches!(
+        proc_b
+            .dt
+            .validate(&actor_b, &proc_b.dt.remove_admin(actor_b)),
+        Err(AclError::LastAdmin)
+    ));
+
+    // a change must follow the version of the entry it replaces
+    let stale = AclOp::Grant {
+        actor: actor_b,
+        class: KvOpClass::Delete,
+        version: 2,
+    };
+    assert!(matches!(
+        proc_b.dt.validate(&actor_b, &stale),
+        Err(AclError::WrongVersion {
+            version: 2,
+            current: 2
+        })
+    ));
+
+    Ok(())
+}
+
+/// Two admins remove each other while one grants actor_c puts, the other revokes them,
+/// and actor_c puts a key.
+fn concurrent_acl_changes() -> (Net<AclKvStore>, Vec<Packet<AclKvOp>>) {
+    let (mut net, actors) = bootstrap_acl_net(4);
+    let (admin_a, admin_b, actor_c) = (actors[0], actors[1], actors[2]);
+    for proc in net.procs.iter_mut() {
+        proc.dt.force_admin(admin_b);
+        let grant = proc.dt.grant(actor_c, KvOpClass::Put);
+        proc.dt.apply(grant);
+    }
+
+    let mut packets = vec![];
+    let mut exec_all = |net: &mut Net<AclKvStore>, actor: &Actor, ops: Vec<AclKvOp>| {
+        for op in ops {
+            packets.extend(net.exec_op(actor, op).expect("Failed to generate op"));
+        }
+    };
+    let proc = |actor| &net.proc(actor).expect("No proc for actor").dt;
+    let a_ops = vec![proc(&admin_a).remove_admin(admin_b)];
+    let b_ops = vec![proc(&admin_b).remove_admin(admin_a)];
+    let c_ops = vec![AclOp::Op(
+        proc(&actor_c).dt.put("motd".to_string(), "hi".to_string()),
+    )];
+    exec_all(&mut net, &admin_a, a_ops);
+    exec_all(&mut net, &admin_b, b_ops);
+    exec_all(&mut net, &actor_c, c_ops);
+    (net, packets)
+}
+
+#[test]
+fn test_concurrent_acl_changes_converge_under_explored_schedules() -> Result<(), &'static str> {
+    let bounds = ExploreBounds {
+        depth: 6,
+        schedules: 200,
+    };
+    let runs = explore(
+        bounds,
+        concurrent_acl_changes,
+        |net: &Net<AclKvStore>, pending: &[Packet<AclKvOp>]| {
+            if !pending.is_empty() {
+                return Ok(());
+            }
+            let first = &net.procs[0].dt;
+            for proc in net.procs.iter() {
+                if proc.dt.acl != first.acl {
+                    return Err(format!("{} ended with a different ACL", proc.actor()));
+                }
+                if !proc.dt.dt.iter().eq(first.dt.iter()) {
+                    return Err(format!("{} ended with a different store", proc.actor()));
+                }
+            }
+            if !net.members_are_in_agreement() {
+                return Err("members disagree".to_string());
+            }
+            Ok(())
+        },
+    )
+    .map_err(|_| "replicas diverged")?;
+    assert!(runs > 1);
+
+    // Under the default order both removals are delivered, the removals commute.
+    let (mut net, packets) = concurrent_acl_changes();
+    net.run_packets_to_completion(packets);
+    for proc in net.procs.iter() {
+        assert!(proc.dt.acl.admins().is_empty());
+        assert!(proc
+            .dt
+            .acl
+            .is_granted(&net.procs[2].actor(), &KvOpClass::Put));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_changes_to_an_entry_resolve_to_the_removal() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_acl_net(3);
+    let (admin_a, admin_b, actor_c) = (actors[0], actors[1], actors[2]);
+    for proc in net.procs.iter_mut() {
+        proc.dt.force_admin(admin_b);
+    }
+
+    // both changes replace version 0 of the same grant
+    let grant = build(&net, &admin_a, |dt| dt.grant(actor_c, KvOpClass::Put))?;
+    let revoke = build(&net, &admin_b, |dt| dt.revoke(actor_c, KvOpClass::Put))?;
+    let mut packets = net
+        .exec_op(&admin_a, grant)
+        .map_err(|_| "Failed to generate op")?;
+    packets.extend(
+        net.exec_op(&admin_b, revoke)
+            .map_err(|_| "Failed to generate op")?,
+    );
+    net.run_packets_to_completion(packets);
+
+    for proc in net.procs.iter() {
+        assert!(!proc.dt.acl.is_granted(&actor_c, &KvOpClass::Put));
+        assert_eq!(proc.dt.acl.grant_version(&actor_c, &KvOpClass::Put), 1);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_ops_signed_before_a_revocation_are_delivered() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_acl_net(3);
+    let admin = actors[0];
+    let actor_b = actors[1];
+    let grant = build(&net, &admin, |dt| dt.grant(actor_b, KvOpClass::Put))?;
+    exec(&mut net, &admin, grant)?;
+
+    // actor_b's put gathers its signatures before the revocation is delivered.
+    let proc_b = net.proc_mut(&actor_b).ok_or("No proc for actor_b")?;
+    let op = AclOp::Op(proc_b.dt.dt.put("motd".to_string(), "hi".to_string()));
+    let packets = proc_b.exec_op(op).map_err(|_| "Failed to generate op")?;
+    let proofs = run_packets_holding_back(&mut net, packets, |p| p.payload.is_proof_of_agreement());
+    assert!(!proofs.is_empty());
+    let revoke = build(&net, &admin, |dt| dt.revoke(actor_b, KvOpClass::Put))?;
+    exec(&mut net, &admin, revoke)?;
+    net.run_packets_to_completion(proofs);
+
+    // The op was permitted by the procs that signed it, so every replica applies it.
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+    for proc in net.procs.iter() {
+        assert!(!proc.dt.acl.is_permitted(&actor_b, &KvOpClass::Put));
+        assert_eq!(proc.dt.dt.get(&"motd".to_string()), Some(&"hi".to_string()));
+        let outcomes: Vec<_> = proc.history_from_source[&actor_b]
+            .iter()
+            .map(|(_msg, _proof, outcome)| *outcome)
+            .collect();
+        assert_eq!(outcomes, vec![DeliveryOutcome::Applied]);
+    }
+
+    // Once the revocation is delivered, actor_b's ops are no longer signed.
+    let proc_b = net.proc(&actor_b).ok_or("No proc for actor_b")?;
+    let op = AclOp::Op(proc_b.dt.dt.put("other".to_string(), "x".to_string()));
+    assert!(matches!(
+        proc_b.dt.validate(&actor_b, &op),
+        Err(AclError::NotPermitted)
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,243 @@
+mod common;
+
+use core::convert::Infallible;
+use std::collections::{BTreeMap, BTreeSet};
+
+use brb::{net::Actor, BRBDataType, DeliveryOutcome, ValidationContext};
+use thiserror::Error;
+
+use common::bootstrap_net;
+
+/// Only accepts ops from voting members that carry the counter of their msg dot.
+#[derive(Debug)]
+struct SequencedDT {
+    actor: Actor,
+    seen: BTreeSet<(Actor, u64)>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+enum SequencedError {
+    #[error("source is not a member")]
+    NotAMember,
+    #[error("op does not match the dot counter")]
+    OutOfSequence,
+}
+
+impl BRBDataType<Actor> for SequencedDT {
+    type Op = u64;
+    type ValidationError = SequencedError;
+
+    fn new(actor: Actor) -> Self {
+        let seen = Default::default();
+        SequencedDT { actor, seen }
+    }
+
+    fn validate(&self, _source: &Actor, _op: &Self::Op) -> Result<(), Self::ValidationError> {
+        Ok(())
+    }
+
+    fn validate_with_context(
+        &self,
+        ctx: &ValidationContext<Actor>,
+        op: &Self::Op,
+    ) -> Result<(), Self::ValidationError> {
+        if !ctx.members.contains(ctx.source) {
+            Err(SequencedError::NotAMember)
+        } else if *op != ctx.dot.counter {
+            Err(SequencedError::OutOfSequence)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn apply(&mut self, op: Self::Op) {
+        self.seen.insert((self.actor, op));
+    }
+}
+
+/// Each source may spend a budget of at most `BUDGET`, overspending is only caught
+/// once the op is delivered.
+#[derive(Debug)]
+struct BudgetDT {
+    spent: BTreeMap<Actor, u64>,
+}
+
+const BUDGET: u64 = 10;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+enum BudgetError {
+    #[error("source has exceeded its budget")]
+    OverBudget,
+}
+
+impl BRBDataType<Actor> for BudgetDT {
+    type Op = u64;
+    type ValidationError = BudgetError;
+
+    fn new(_actor: Actor) -> Self {
+        BudgetDT {
+            spent: Default::default(),
+        }
+    }
+
+    fn validate(&self, _source: &Actor, _op: &Self::Op) -> Result<(), Self::ValidationError> {
+        Ok(())
+    }
+
+    fn apply(&mut self, _op: Self::Op) {
+        panic!("BudgetDT ops must go through try_apply");
+    }
+
+    fn try_apply(
+        &mut self,
+        ctx: &ValidationContext<Actor>,
+        op: Self::Op,
+    ) -> Result<(), Self::ValidationError> {
+        let spent = self.spent.entry(*ctx.source).or_default();
+        if *spent + op > BUDGET {
+            Err(BudgetError::OverBudget)
+        } else {
+            *spent += op;
+            Ok(())
+        }
+    }
+}
+
+/// Records the context each op was delivered in.
+#[derive(Debug)]
+struct ContextRecorderDT {
+    contexts: Vec<(u64, u64, usize)>,
+}
+
+impl BRBDataType<Actor> for ContextRecorderDT {
+    type Op = u8;
+    type ValidationError = Infallible;
+
+    fn new(_actor: Actor) -> Self {
+        ContextRecorderDT {
+            contexts: Default::default(),
+        }
+    }
+
+    fn validate(&self, _source: &Actor, _op: &Self::Op) -> Result<(), Self::ValidationError> {
+        Ok(())
+    }
+
+    fn apply(&mut self, _op: Self::Op) {
+        panic!("ContextRecorderDT ops must go through try_apply");
+    }
+
+    fn try_apply(
+        &mut self,
+        ctx: &ValidationContext<Actor>,
+        _op: Self::Op,
+    ) -> Result<(), Self::ValidationError> {
+        self.contexts.push((
+            ctx.dot.counter,
+            ctx.delivered.get(ctx.source),
+            ctx.members.len(),
+        ));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_validate_with_context() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<SequencedDT>(3);
+    let actor_a = actors[0];
+
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(1)
+        .map_err(|_| "Failed to generate op")?;
+    net.run_packets_to_completion(packets);
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+
+    // The second msg from actor_a has dot counter 2, actor_a validates its own op
+    // before asking the network.
+    assert!(net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(3)
+        .is_err());
+
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(2)
+        .map_err(|_| "Failed to generate op")?;
+    net.run_packets_to_completion(packets);
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert_eq!(
+        net.proc(&actor_a)
+            .ok_or("No proc for actor_a")?
+            .delivered
+            .get(&actor_a),
+        2
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_ops_rejected_at_delivery_are_recorded_in_history() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<BudgetDT>(3);
+    let actor_a = actors[0];
+
+    for op in &[6, 6, 4] {
+        let packets = net
+            .proc_mut(&actor_a)
+            .ok_or("No proc for actor_a")?
+            .exec_op(*op)
+            .map_err(|_| "Failed to generate op")?;
+        net.run_packets_to_completion(packets);
+    }
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+
+    for proc in net.procs.iter() {
+        // All three msgs were delivered, but the second one was over budget.
+        assert_eq!(proc.delivered.get(&actor_a), 3);
+        assert_eq!(proc.dt.spent.get(&actor_a), Some(&10));
+
+        let outcomes: Vec<_> = proc.history_from_source[&actor_a]
+            .iter()
+            .map(|(_msg, _proof, outcome)| *outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                DeliveryOutcome::Applied,
+                DeliveryOutcome::RejectedByDataType,
+                DeliveryOutcome::Applied
+            ]
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_delivery_context_excludes_the_msg_being_delivered() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<ContextRecorderDT>(3);
+    let actor_a = actors[0];
+
+    for op in 0..2 {
+        let packets = net
+            .proc_mut(&actor_a)
+            .ok_or("No proc for actor_a")?
+            .exec_op(op)
+            .map_err(|_| "Failed to generate op")?;
+        net.run_packets_to_completion(packets);
+    }
+    assert_eq!(net.count_invalid_packets(), 0);
+
+    for proc in net.procs.iter() {
+        // (dot counter, delivered from the source before this msg, members of the msg's gen)
+        assert_eq!(proc.dt.contexts, vec![(1, 0, 3), (2, 1, 3)]);
+    }
+
+    Ok(())
+}
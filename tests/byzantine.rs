@@ -0,0 +1,127 @@
+mod common;
+
+use std::collections::BTreeSet;
+
+use brb::net::{
+    byzantine::{Equivocate, ForgeProof, GarbageVotes, Replay, SignEverything, Silent},
+    Actor, Byzantine,
+};
+
+use common::{bootstrap_net, TestDT, TestNet};
+
+/// Bootstraps a network of 4 procs where the last one follows the given behaviour.
+fn bootstrap_byzantine_net(
+    behaviour: impl Byzantine<TestDT> + 'static,
+) -> (TestNet, Vec<Actor>, Actor) {
+    let (mut net, actors) = bootstrap_net::<TestDT>(4);
+    let byzantine = actors[3];
+    net.make_byzantine(byzantine, behaviour);
+    (net, actors[..3].to_vec(), byzantine)
+}
+
+fn exec(net: &mut TestNet, actor: &Actor, op: u8) {
+    let packets = net.exec_op(actor, op).expect("Failed to generate op");
+    net.run_packets_to_completion(packets);
+}
+
+/// Every honest proc delivered exactly the ops of the honest procs.
+fn assert_honest_ops_delivered(net: &TestNet, honest: &[Actor]) {
+    assert!(net.members_are_in_agreement());
+    let expected: BTreeSet<u8> = (0..honest.len() as u8).collect();
+    for actor in honest {
+        assert_eq!(net.proc(actor).unwrap().dt.set, expected);
+    }
+}
+
+fn exec_honest_ops(net: &mut TestNet, honest: &[Actor]) {
+    for (op, actor) in honest.iter().enumerate() {
+        exec(net, actor, op as u8);
+    }
+}
+
+#[test]
+fn test_silent_proc() {
+    let (mut net, honest, byzantine) = bootstrap_byzantine_net(Silent);
+    exec_honest_ops(&mut net, &honest);
+    exec(&mut net, &byzantine, 42);
+
+    assert_honest_ops_delivered(&net, &honest);
+    assert_eq!(net.count_invalid_packets(), 0);
+}
+
+#[test]
+fn test_proc_signing_everything() {
+    let (mut net, honest, byzantine) = bootstrap_byzantine_net(SignEverything);
+    exec_honest_ops(&mut net, &honest);
+
+    assert_honest_ops_delivered(&net, &honest);
+    assert!(net.proc(&byzantine).unwrap().dt.set.len() == honest.len());
+}
+
+#[test]
+fn test_equivocating_initiator() {
+    let (mut net, honest, byzantine) = bootstrap_byzantine_net(Equivocate { alternative: 43 });
+    exec(&mut net, &byzantine, 42);
+    exec_honest_ops(&mut net, &honest);
+
+    // Each honest proc only sees one of the ops, and honest procs only send their
+    // signatures back to the source, so no proc ever holds both ops to reject. The
+    // equivocation shows up as neither op gathering a proof, not as invalid packets.
+    assert_honest_ops_delivered(&net, &honest);
+    assert_eq!(net.count_invalid_packets(), 0);
+    for actor in honest.iter() {
+        let proc = net.proc(actor).unwrap();
+        assert_eq!(proc.delivered.get(&byzantine), 0);
+        assert_eq!(proc.reputation.penalty(&byzantine, proc.packets_handled), 0);
+    }
+}
+
+#[test]
+fn test_proc_forging_proofs() {
+    let (mut net, honest, byzantine) = bootstrap_byzantine_net(ForgeProof);
+    exec(&mut net, &byzantine, 42);
+    exec(&mut net, &byzantine, 43);
+    exec_honest_ops(&mut net, &honest);
+
+    // Each forged proof is for a new msg.
+    assert_eq!(net.proc(&byzantine).unwrap().received.get(&byzantine), 2);
+
+    assert_honest_ops_delivered(&net, &honest);
+    for actor in honest.iter() {
+        assert!(net.invalid_packets[actor] > 0);
+        assert_eq!(net.proc(actor).unwrap().delivered.get(&byzantine), 0);
+    }
+}
+
+#[test]
+fn test_proc_replaying_packets() {
+    let (mut net, honest, byzantine) = bootstrap_byzantine_net(Replay::default());
+    exec_honest_ops(&mut net, &honest);
+    exec(&mut net, &byzantine, 3);
+
+    assert!(net.members_are_in_agreement());
+    assert!(net.count_invalid_packets() > 0);
+    for actor in honest.iter() {
+        assert_eq!(net.proc(actor).unwrap().dt.set, (0..4).collect());
+    }
+}
+
+#[test]
+fn test_proc_sending_garbage_membership_votes() {
+    let (mut net, honest, byzantine) = bootstrap_byzantine_net(GarbageVotes::default());
+    let gen = net.proc(&byzantine).unwrap().membership.gen;
+    exec_honest_ops(&mut net, &honest);
+
+    assert!(net.count_invalid_packets() > 0);
+
+    // The votes are refused and the byzantine proc's own membership state is untouched.
+    assert_honest_ops_delivered(&net, &honest);
+    let proc = net.proc(&byzantine).unwrap();
+    assert_eq!(proc.membership.gen, gen);
+    assert_eq!(proc.membership.pending_gen, gen);
+    for actor in honest.iter() {
+        let proc = net.proc(actor).unwrap();
+        assert_eq!(proc.membership.gen, 0);
+        assert_eq!(proc.peers().unwrap().len(), 4);
+    }
+}
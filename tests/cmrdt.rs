@@ -0,0 +1,137 @@
+mod common;
+
+use brb::{net::Actor, AcceptAll, BRBCmRDT, SourceIsAuthor};
+use crdts::{GCounter, Map, Orswot};
+
+use common::bootstrap_net;
+
+type BRBOrswot = BRBCmRDT<Actor, Orswot<u8, Actor>, SourceIsAuthor>;
+type BRBMap = BRBCmRDT<Actor, Map<u8, Orswot<u8, Actor>, Actor>, SourceIsAuthor>;
+type BRBGCounter = BRBCmRDT<Actor, GCounter<Actor>, AcceptAll>;
+
+#[test]
+fn test_orswot_over_brb() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<BRBOrswot>(3);
+
+    for (member, actor) in actors.iter().enumerate() {
+        let proc = net.proc_mut(actor).ok_or("No proc for actor")?;
+        let add_ctx = proc.dt.crdt.read_ctx().derive_add_ctx(*actor);
+        let op = proc.dt.crdt.add(member as u8, add_ctx);
+        let packets = proc
+            .exec_op(op.into())
+            .map_err(|_| "Failed to generate op")?;
+        net.run_packets_to_completion(packets);
+    }
+
+    let actor_a = actors[0];
+    let proc_a = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
+    let rm_ctx = proc_a.dt.crdt.contains(&0).derive_rm_ctx();
+    let op = proc_a.dt.crdt.rm(0, rm_ctx);
+    let packets = proc_a
+        .exec_op(op.into())
+        .map_err(|_| "Failed to generate op")?;
+    net.run_packets_to_completion(packets);
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+    for proc in net.procs.iter() {
+        assert_eq!(proc.dt.crdt.read().val, vec![1, 2].into_iter().collect());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_orswot_rejects_ops_authored_by_another_actor() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<BRBOrswot>(3);
+    let actor_a = actors[0];
+    let actor_b = actors[1];
+
+    // actor_b tries to submit an op in actor_a's name
+    let proc_b = net.proc_mut(&actor_b).ok_or("No proc for actor_b")?;
+    let add_ctx = proc_b.dt.crdt.read_ctx().derive_add_ctx(actor_a);
+    let op = proc_b.dt.crdt.add(0, add_ctx);
+    assert!(proc_b.exec_op(op.into()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_orswot_rejects_removing_what_another_actor_added() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<BRBOrswot>(3);
+    let actor_a = actors[0];
+    let actor_b = actors[1];
+
+    let proc_b = net.proc_mut(&actor_b).ok_or("No proc for actor_b")?;
+    let add_ctx = proc_b.dt.crdt.read_ctx().derive_add_ctx(actor_b);
+    let op = proc_b.dt.crdt.add(0, add_ctx);
+    let packets = proc_b
+        .exec_op(op.into())
+        .map_err(|_| "Failed to generate op")?;
+    net.run_packets_to_completion(packets);
+
+    // actor_a tries to remove the member actor_b added
+    let proc_a = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
+    let rm_ctx = proc_a.dt.crdt.contains(&0).derive_rm_ctx();
+    let op = proc_a.dt.crdt.rm(0, rm_ctx);
+    assert!(proc_a.exec_op(op.into()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_map_over_brb() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<BRBMap>(3);
+
+    for actor in actors.iter() {
+        let proc = net.proc_mut(actor).ok_or("No proc for actor")?;
+        let add_ctx = proc.dt.crdt.read_ctx().derive_add_ctx(*actor);
+        let op = proc
+            .dt
+            .crdt
+            .update(7, add_ctx, |set, ctx| set.add(actors.len() as u8, ctx));
+        let packets = proc
+            .exec_op(op.into())
+            .map_err(|_| "Failed to generate op")?;
+        net.run_packets_to_completion(packets);
+    }
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+    for proc in net.procs.iter() {
+        let set = proc.dt.crdt.get(&7).val.ok_or("No value for key")?;
+        assert_eq!(set.read().val, vec![3].into_iter().collect());
+        for actor in actors.iter() {
+            assert_eq!(set.clock().get(actor), 1);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gcounter_over_brb() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<BRBGCounter>(3);
+
+    for _ in 0..2 {
+        for actor in actors.iter() {
+            let proc = net.proc_mut(actor).ok_or("No proc for actor")?;
+            let op = proc.dt.crdt.inc(*actor);
+            let packets = proc
+                .exec_op(op.into())
+                .map_err(|_| "Failed to generate op")?;
+            net.run_packets_to_completion(packets);
+        }
+    }
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+    for proc in net.procs.iter() {
+        for actor in actors.iter() {
+            // each actor has incremented twice, its next increment is the third
+            assert_eq!(proc.dt.crdt.inc(*actor).counter, 3);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use brb::{
+    combinators::RegistryError,
+    net::{Actor, Net},
+    BRBCmRDT, BRBDataType, BRBKvStore, CmRDTOp, Product, ProductOp, Registry, RegistryOp,
+};
+use crdts::GCounter;
+use serde::{ser, Serialize, Serializer};
+
+use common::bootstrap_net;
+
+type KvStore = BRBKvStore<Actor, String, String>;
+type Counter = BRBCmRDT<Actor, GCounter<Actor>>;
+
+/// An op that always fails to serialize.
+struct Unserializable;
+
+impl Serialize for Unserializable {
+    fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom("unserializable"))
+    }
+}
+
+fn exec<DT: BRBDataType<Actor>>(
+    net: &mut Net<DT>,
+    actor: &Actor,
+    op: DT::Op,
+) -> Result<(), &'static str> {
+    let packets = net
+        .proc_mut(actor)
+        .ok_or("No proc for actor")?
+        .exec_op(op)
+        .map_err(|_| "Failed to generate op")?;
+    net.run_packets_to_completion(packets);
+    Ok(())
+}
+
+#[test]
+fn test_product_dispatches_ops_to_each_side() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<Product<KvStore, Counter>>(3);
+    let actor_a = actors[0];
+
+    let dt = &net.proc(&actor_a).ok_or("No proc for actor_a")?.dt;
+    let op = ProductOp::Left(dt.left.put("name".to_string(), "brb".to_string()));
+    exec(&mut net, &actor_a, op)?;
+
+    for actor in actors.iter() {
+        let dt = &net.proc(actor).ok_or("No proc for actor")?.dt;
+        let op = ProductOp::Right(CmRDTOp(dt.right.crdt.inc(*actor)));
+        exec(&mut net, actor, op)?;
+    }
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+    for proc in net.procs.iter() {
+        assert_eq!(
+            proc.dt.left.get(&"name".to_string()),
+            Some(&"brb".to_string())
+        );
+        for actor in actors.iter() {
+            assert_eq!(proc.dt.right.crdt.inc(*actor).counter, 2);
+        }
+    }
+
+    // each side keeps validating its own ops
+    let proc_b = net.proc_mut(&actors[1]).ok_or("No proc for actor_b")?;
+    let op = ProductOp::Left(proc_b.dt.left.delete("name".to_string()));
+    assert!(proc_b.exec_op(op).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_registry_hosts_named_data_types() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<Registry<Actor>>(3);
+    let actor_a = actors[0];
+
+    for proc in net.procs.iter_mut() {
+        proc.dt.register::<KvStore>("config");
+        proc.dt.register::<KvStore>("secrets");
+        proc.dt.register::<Counter>("counter");
+    }
+
+    let registry = &net.proc(&actor_a).ok_or("No proc for actor_a")?.dt;
+    let config = registry.get::<KvStore>("config").ok_or("No config")?;
+    let op = Registry::<Actor>::op("config", &config.put("a".to_string(), "1".to_string()))
+        .map_err(|_| "Failed to serialize op")?;
+    exec(&mut net, &actor_a, op)?;
+
+    let registry = &net.proc(&actor_a).ok_or("No proc for actor_a")?.dt;
+    let counter = registry.get::<Counter>("counter").ok_or("No counter")?;
+    let op = Registry::<Actor>::op("counter", &CmRDTOp(counter.crdt.inc(actor_a)))
+        .map_err(|_| "Failed to serialize op")?;
+    exec(&mut net, &actor_a, op)?;
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+    for proc in net.procs.iter() {
+        let config = proc.dt.get::<KvStore>("config").ok_or("No config")?;
+        assert_eq!(config.get(&"a".to_string()), Some(&"1".to_string()));
+        assert!(proc
+            .dt
+            .get::<KvStore>("secrets")
+            .ok_or("No secrets")?
+            .is_empty());
+        let counter = proc.dt.get::<Counter>("counter").ok_or("No counter")?;
+        assert_eq!(counter.crdt.inc(actor_a).counter, 2);
+
+        // a data type can only be read back as its own type
+        assert!(proc.dt.get::<Counter>("config").is_none());
+        assert_eq!(
+            proc.dt.names().collect::<Vec<_>>(),
+            vec!["config", "counter", "secrets"]
+        );
+    }
+
+    let proc_a = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
+    let op = Registry::<Actor>::op("ledger", &0u64).map_err(|_| "Failed to serialize op")?;
+    assert!(proc_a.exec_op(op).is_err());
+    let op = RegistryOp {
+        name: "counter".to_string(),
+        op: vec![0xff],
+    };
+    assert!(proc_a.exec_op(op).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_registry_op_surfaces_serialization_errors() -> Result<(), &'static str> {
+    assert!(matches!(
+        Registry::<Actor>::op("config", &Unserializable),
+        Err(RegistryError::Serialize { name, .. }) if name == "config"
+    ));
+
+    Ok(())
+}
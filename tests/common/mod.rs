@@ -0,0 +1,76 @@
+//! Helpers shared by the integration tests.
+//!
+//! Each test crate only uses some of these.
+#![allow(dead_code)]
+
+use core::convert::Infallible;
+use std::collections::BTreeSet;
+
+use brb::{
+    net::{Actor, Net, Packet, State},
+    BRBDataType,
+};
+
+/// A set of bytes that accepts every op.
+#[derive(Debug)]
+pub struct TestDT {
+    pub set: BTreeSet<u8>,
+}
+
+impl BRBDataType<Actor> for TestDT {
+    type Op = u8;
+    type ValidationError = Infallible;
+
+    fn new(_actor: Actor) -> Self {
+        let set = Default::default();
+        TestDT { set }
+    }
+
+    fn validate(&self, _source: &Actor, _op: &Self::Op) -> Result<(), Self::ValidationError> {
+        Ok(())
+    }
+
+    fn apply(&mut self, op: Self::Op) {
+        self.set.insert(op);
+    }
+}
+
+pub type TestNet = Net<TestDT>;
+
+/// Creates a network of n_procs genesis members.
+pub fn bootstrap_net<DT: BRBDataType<Actor>>(n_procs: usize) -> (Net<DT>, Vec<Actor>) {
+    let mut net = Net::new();
+    let actors = net.initialize_members(n_procs);
+    (net, actors)
+}
+
+/// Delivers packets until there are none left, returning the packets matching `hold_back`
+/// instead of delivering them.
+pub fn run_packets_holding_back<DT: BRBDataType<Actor>>(
+    net: &mut Net<DT>,
+    mut packets: Vec<Packet<DT::Op>>,
+    hold_back: impl Fn(&Packet<DT::Op>) -> bool,
+) -> Vec<Packet<DT::Op>> {
+    let mut held_back = Vec::new();
+    while !packets.is_empty() {
+        let packet = packets.remove(0);
+        if hold_back(&packet) {
+            held_back.push(packet);
+        } else {
+            packets.extend(net.deliver_packet(packet));
+        }
+    }
+    held_back
+}
+
+/// Makes proc forget the msgs it has sent so that its next op re-uses their dots,
+/// i.e. the proc equivocates.
+///
+/// The proc validates its own msgs too, so it also forgets its signature for its
+/// pending msg, otherwise it would refuse to sign the conflicting one.
+pub fn forget_sent_msgs<DT: BRBDataType<Actor>>(proc: &mut State<DT>) {
+    let actor = proc.actor();
+    proc.received = Default::default();
+    proc.delivered = Default::default();
+    proc.signed_validations.remove(&actor);
+}
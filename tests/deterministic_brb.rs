@@ -1,60 +1,21 @@
-use core::convert::Infallible;
-use std::collections::BTreeSet;
+mod common;
 
+use brb::membership::Reconfig;
 use brb::{
     deterministic_brb::{Msg, Op},
-    net::{Actor, Net},
-    BRBDataType, Payload,
+    net::{Actor, Packet, Scenario, Sig},
+    Error, LimitedPayload, Payload, RateLimit, RateLimits, RejectionReason, Reputation,
+    ReputationConfig, Severity, ValidationError,
 };
 use crdts::Dot;
 
-#[derive(Debug)]
-struct TestDT {
-    actor: Actor,
-    set: BTreeSet<u8>,
-}
-
-impl BRBDataType<Actor> for TestDT {
-    type Op = u8;
-    type ValidationError = Infallible;
-
-    fn new(actor: Actor) -> Self {
-        let set = Default::default();
-        TestDT { actor, set }
-    }
-
-    fn validate(&self, _source: &Actor, _op: &Self::Op) -> Result<(), Self::ValidationError> {
-        Ok(())
-    }
-
-    fn apply(&mut self, op: Self::Op) {
-        self.set.insert(op);
-    }
-}
-
-type TestNet = Net<TestDT>;
+use common::{forget_sent_msgs, run_packets_holding_back, TestDT, TestNet};
 
 #[test]
+#[allow(clippy::iter_overeager_cloned)]
 fn test_resend_msgs() -> Result<(), &'static str> {
-    let mut net = TestNet::new();
-    let actor_a = net.initialize_proc();
-    let actor_b = net.initialize_proc();
-    let actor_c = net.initialize_proc();
-
-    let a_proc = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
-    a_proc.force_join(actor_a);
-    a_proc.force_join(actor_b);
-    a_proc.force_join(actor_c);
-
-    let b_proc = net.proc_mut(&actor_b).ok_or("No proc for actor_b")?;
-    b_proc.force_join(actor_a);
-    b_proc.force_join(actor_b);
-    b_proc.force_join(actor_c);
-
-    let c_proc = net.proc_mut(&actor_c).ok_or("No proc for actor_c")?;
-    c_proc.force_join(actor_a);
-    c_proc.force_join(actor_b);
-    c_proc.force_join(actor_c);
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let actor_a = actors[0];
 
     let mut packets = net
         .proc_mut(&actor_a)
@@ -222,3 +183,999 @@ fn test_resend_msgs() -> Result<(), &'static str> {
 
     Ok(())
 }
+
+#[test]
+fn test_duplicate_request_validation_is_resigned() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let (actor_a, actor_b) = (actors[0], actors[1]);
+
+    let req_packet = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(32u8)
+        .map_err(|_| "Failed to generate insert op")?
+        .into_iter()
+        .find(|p| p.dest == actor_b)
+        .ok_or("No request for actor_b")?;
+
+    // The SignedValidated response was lost, the resent request gets the same signature.
+    let sig_packets = net.deliver_packet(req_packet.clone());
+    assert_eq!(sig_packets.len(), 1);
+    assert_eq!(net.deliver_packet(req_packet), sig_packets);
+    assert_eq!(net.count_invalid_packets(), 0);
+
+    // A different msg for the same dot is still refused. We make actor_a forget the
+    // first msg so that it re-uses the dot.
+    let a_proc = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
+    forget_sent_msgs(a_proc);
+    let conflicting_packet = a_proc
+        .exec_op(33u8)
+        .map_err(|_| "Failed to generate insert op")?
+        .into_iter()
+        .find(|p| p.dest == actor_b)
+        .ok_or("No request for actor_b")?;
+
+    assert_eq!(net.deliver_packet(conflicting_packet), vec![]);
+    assert_eq!(net.count_invalid_packets(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_late_signatures_do_not_start_a_new_proof() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(4).setup::<TestDT>();
+    let (actor_a, actor_d) = (actors[0], actors[3]);
+
+    // actor_d's signature arrives after actor_a has gathered a proof and delivered.
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(32u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    let late_sigs = run_packets_holding_back(&mut net, packets, |p| {
+        p.source == actor_d && p.payload.kind() == "signed_validated"
+    });
+    assert_eq!(late_sigs.len(), 1);
+    assert!(net.members_are_in_agreement());
+
+    for late_sig in late_sigs {
+        assert_eq!(net.deliver_packet(late_sig), vec![]);
+    }
+    assert!(net
+        .proc(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .pending_proof
+        .is_empty());
+    assert_eq!(net.count_invalid_packets(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_signatures_for_msgs_from_a_past_generation_are_forgotten() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let (actor_a, actor_b) = (actors[0], actors[1]);
+
+    // actor_b signs a msg from actor_a that never gathers a proof.
+    let req_packet = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(32u8)
+        .map_err(|_| "Failed to generate insert op")?
+        .into_iter()
+        .find(|p| p.dest == actor_b)
+        .ok_or("No request for actor_b")?;
+    net.deliver_packet(req_packet);
+    let b_proc = net.proc(&actor_b).ok_or("No proc for actor_b")?;
+    assert!(b_proc.signed_validations.contains_key(&actor_a));
+
+    // Once the generation moves on, actor_b forgets its signature.
+    let actor_d = net.initialize_proc();
+    let packets = net
+        .propose(&actor_b, Reconfig::Join(actor_d))
+        .map_err(|_| "Failed to propose join")?;
+    net.run_packets_to_completion(packets);
+
+    let b_proc = net.proc(&actor_b).ok_or("No proc for actor_b")?;
+    assert_eq!(b_proc.membership.gen, 1);
+    assert!(b_proc.signed_validations.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_proof_of_agreement_is_reacknowledged() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let actor_a = actors[0];
+
+    let req_packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(32u8)
+        .map_err(|_| "Failed to generate insert op")?;
+
+    let sig_packets: Vec<_> = req_packets
+        .into_iter()
+        .flat_map(|p| net.deliver_packet(p))
+        .collect();
+    let proof_packets: Vec<_> = sig_packets
+        .into_iter()
+        .flat_map(|p| net.deliver_packet(p))
+        .collect();
+    assert_eq!(proof_packets.len(), 3);
+
+    // Deliver the proofs but drop all of the Delivered acks.
+    for packet in proof_packets {
+        assert_eq!(net.deliver_packet(packet).len(), 1);
+    }
+
+    let resent_proofs = net
+        .proc(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .resend_pending_deliveries()
+        .map_err(|_| "Failed to resend pending deliveries")?;
+    assert_eq!(resent_proofs.len(), 3);
+
+    net.run_packets_to_completion(resent_proofs);
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net
+        .proc(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .pending_delivery
+        .is_empty());
+    assert!(net.members_are_in_agreement());
+
+    Ok(())
+}
+
+#[test]
+#[allow(clippy::result_large_err)]
+fn test_relayed_proof_of_agreement_is_not_reacknowledged() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let (actor_a, actor_b, actor_c) = (actors[0], actors[1], actors[2]);
+
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(32u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    net.run_packets_to_completion(packets);
+
+    // actor_b asks its peers for history it has already delivered.
+    let b_proc = net.proc_mut(&actor_b).ok_or("No proc for actor_b")?;
+    let delivered = std::mem::take(&mut b_proc.delivered);
+    let anti_entropy = [actor_a, actor_c]
+        .iter()
+        .map(|peer| b_proc.anti_entropy(*peer))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| "Failed to generate anti-entropy packets")?;
+    b_proc.delivered = delivered;
+
+    let n_delivered = net.delivered_packets.len();
+    net.run_packets_to_completion(anti_entropy);
+
+    // The proof relayed by actor_c is ignored, the one resent by actor_a is re-acknowledged
+    // and actor_a ignores the late ack since it has already committed the msg.
+    let acks: Vec<_> = net.delivered_packets[n_delivered..]
+        .iter()
+        .filter(|p| matches!(p.payload, Payload::BRB(Op::Delivered { .. })))
+        .collect();
+    assert_eq!(acks.len(), 1);
+    assert_eq!(acks[0].dest, actor_a);
+    assert_eq!(net.count_invalid_packets(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_out_of_order_proof_of_agreement_is_buffered() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(4).setup::<TestDT>();
+    let (actor_a, actor_c) = (actors[0], actors[2]);
+
+    // actor_c is cut off while actor_a gets two ops agreed on by the rest of the network.
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(1u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    let mut held_back = run_packets_holding_back(&mut net, packets, |p| p.dest == actor_c);
+
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(2u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    held_back.extend(run_packets_holding_back(&mut net, packets, |p| {
+        p.dest == actor_c
+    }));
+
+    let mut proofs: Vec<_> = held_back
+        .into_iter()
+        .filter(|p| p.payload.is_proof_of_agreement())
+        .collect();
+    assert_eq!(proofs.len(), 2);
+    let proof_2 = proofs.pop().ok_or("Failed to pop proof")?;
+    let proof_1 = proofs.pop().ok_or("Failed to pop proof")?;
+
+    // The second proof arrives first, it's held until the first one shows up.
+    assert_eq!(net.deliver_packet(proof_2), vec![]);
+    assert_eq!(net.count_invalid_packets(), 0);
+    let c_proc = net.proc(&actor_c).ok_or("No proc for actor_c")?;
+    assert_eq!(c_proc.delivered.get(&actor_a), 0);
+    assert_eq!(c_proc.reorder_buffer[&actor_a].len(), 1);
+
+    let acks = net.deliver_packet(proof_1);
+    assert_eq!(acks.len(), 2);
+    let c_proc = net.proc(&actor_c).ok_or("No proc for actor_c")?;
+    assert_eq!(c_proc.delivered.get(&actor_a), 2);
+    assert!(c_proc.reorder_buffer.is_empty());
+
+    // actor_a already has a super-majority of acks for both msgs, so it's fine for
+    // these to be rejected.
+    net.run_packets_to_completion(acks);
+    assert!(net.members_are_in_agreement());
+
+    Ok(())
+}
+
+#[test]
+fn test_proof_of_agreement_from_a_future_generation_is_not_buffered() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(5).setup::<TestDT>();
+    let (actor_a, actor_c, actor_e) = (actors[0], actors[2], actors[4]);
+    net.proc_mut(&actor_c)
+        .ok_or("No proc for actor_c")?
+        .auto_catch_up = true;
+
+    // actor_c misses the generation change and the proof of the first msg of generation 1.
+    let packets = net
+        .propose(&actor_a, Reconfig::Leave(actor_e))
+        .map_err(|_| "Failed to propose leave")?;
+    run_packets_holding_back(&mut net, packets, |p| p.dest == actor_c);
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(1u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    let proof = run_packets_holding_back(&mut net, packets, |p| p.dest == actor_c)
+        .into_iter()
+        .find(|p| p.payload.is_proof_of_agreement())
+        .ok_or("No proof for actor_c")?;
+
+    // We can't check the signatures of a proof for a generation we don't know yet,
+    // so instead of buffering it actor_c asks to catch up.
+    let catch_up = net.deliver_packet(proof);
+    let c_proc = net.proc(&actor_c).ok_or("No proc for actor_c")?;
+    assert_eq!(c_proc.membership.gen, 0);
+    assert!(c_proc.reorder_buffer.is_empty());
+    assert_eq!(catch_up.len(), 1);
+
+    net.run_packets_to_completion(catch_up);
+    let c_proc = net.proc(&actor_c).ok_or("No proc for actor_c")?;
+    assert_eq!(c_proc.membership.gen, 1);
+    assert_eq!(c_proc.delivered.get(&actor_a), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_reorder_buffer_is_capped_across_sources() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(4).setup::<TestDT>();
+    let (actor_a, actor_c) = (actors[0], actors[2]);
+    net.proc_mut(&actor_c)
+        .ok_or("No proc for actor_c")?
+        .reorder_buffer_total_limit = 1;
+
+    // actor_c is cut off while actor_a gets three ops agreed on by the rest of the network.
+    let mut proofs = Vec::new();
+    for op in 1..=3u8 {
+        let packets = net
+            .proc_mut(&actor_a)
+            .ok_or("No proc for actor_a")?
+            .exec_op(op)
+            .map_err(|_| "Failed to generate insert op")?;
+        proofs.extend(
+            run_packets_holding_back(&mut net, packets, |p| p.dest == actor_c)
+                .into_iter()
+                .filter(|p| p.payload.is_proof_of_agreement()),
+        );
+    }
+    assert_eq!(proofs.len(), 3);
+    let proof_3 = proofs.pop().ok_or("Failed to pop proof")?;
+    let proof_2 = proofs.pop().ok_or("Failed to pop proof")?;
+    let proof_1 = proofs.pop().ok_or("Failed to pop proof")?;
+
+    // Only one early proof fits in the buffer, the other is rejected.
+    net.deliver_packet(proof_2);
+    net.deliver_packet(proof_3);
+    let c_proc = net.proc(&actor_c).ok_or("No proc for actor_c")?;
+    assert_eq!(c_proc.reorder_buffer[&actor_a].len(), 1);
+    assert_eq!(net.count_invalid_packets(), 1);
+
+    net.deliver_packet(proof_1);
+    let c_proc = net.proc(&actor_c).ok_or("No proc for actor_c")?;
+    assert_eq!(c_proc.delivered.get(&actor_a), 2);
+    assert!(c_proc.reorder_buffer.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_rejected_packets_are_reported_to_source() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let (actor_a, actor_b) = (actors[0], actors[1]);
+    net.send_rejections = true;
+
+    let req_packet = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(32u8)
+        .map_err(|_| "Failed to generate insert op")?
+        .into_iter()
+        .find(|p| p.dest == actor_b)
+        .ok_or("No request for actor_b")?;
+    assert_eq!(net.deliver_packet(req_packet).len(), 1);
+
+    // actor_a forgets about the first msg and asks actor_b to sign a conflicting one.
+    let a_proc = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
+    forget_sent_msgs(a_proc);
+    let conflicting_packet = a_proc
+        .exec_op(33u8)
+        .map_err(|_| "Failed to generate insert op")?
+        .into_iter()
+        .find(|p| p.dest == actor_b)
+        .ok_or("No request for actor_b")?;
+
+    let rejections = net.deliver_packet(conflicting_packet);
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].dest, actor_a);
+    assert_eq!(
+        rejections[0].payload,
+        Payload::Rejected {
+            reason: RejectionReason::Conflict,
+            dot: Some(Dot::new(actor_a, 1)),
+        }
+    );
+
+    // Rejections are never answered with another rejection.
+    assert_eq!(net.deliver_packet(rejections[0].clone()), vec![]);
+    assert_eq!(net.count_invalid_packets(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_rejections_for_msgs_ahead_or_behind() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(4).setup::<TestDT>();
+    let (actor_a, actor_b) = (actors[0], actors[1]);
+    net.send_rejections = true;
+
+    // actor_b misses the first msg from actor_a entirely.
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(1u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    let old_req_packet = packets
+        .iter()
+        .find(|p| p.dest == actor_b)
+        .cloned()
+        .ok_or("No request for actor_b")?;
+    run_packets_holding_back(&mut net, packets, |p| p.dest == actor_b);
+
+    // The request for the second msg is ahead of what actor_b expects from actor_a.
+    let req_packet = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(2u8)
+        .map_err(|_| "Failed to generate insert op")?
+        .into_iter()
+        .find(|p| p.dest == actor_b)
+        .ok_or("No request for actor_b")?;
+    let rejections = net.deliver_packet(req_packet);
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].dest, actor_a);
+    assert_eq!(
+        rejections[0].payload,
+        Payload::Rejected {
+            reason: RejectionReason::Ahead,
+            dot: Some(Dot::new(actor_a, 2)),
+        }
+    );
+
+    // actor_b is the one lagging behind, there's nothing for actor_a to do.
+    assert_eq!(net.deliver_packet(rejections[0].clone()), vec![]);
+
+    // Once actor_b has caught up, the request for the first msg is behind.
+    let b_proc = net.proc(&actor_b).ok_or("No proc for actor_b")?;
+    let anti_entropy = b_proc
+        .anti_entropy(actor_a)
+        .map_err(|_| "Failed to generate anti-entropy")?;
+    net.run_packets_to_completion(vec![anti_entropy]);
+    assert_eq!(
+        net.proc(&actor_b)
+            .ok_or("No proc for actor_b")?
+            .delivered
+            .get(&actor_a),
+        1
+    );
+
+    let rejections = net.deliver_packet(old_req_packet);
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(
+        rejections[0].payload,
+        Payload::Rejected {
+            reason: RejectionReason::Behind,
+            dot: Some(Dot::new(actor_a, 1)),
+        }
+    );
+
+    // A source told it's behind asks to be brought up to date.
+    let reactions = net.deliver_packet(rejections[0].clone());
+    assert_eq!(reactions.len(), 1);
+    assert_eq!(reactions[0].dest, actor_b);
+    assert!(matches!(reactions[0].payload, Payload::AntiEntropy { .. }));
+
+    Ok(())
+}
+
+#[test]
+fn test_rejection_for_a_msg_from_a_past_generation() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(5).setup::<TestDT>();
+    let (actor_a, actor_c, actor_e) = (actors[0], actors[2], actors[4]);
+    net.send_rejections = true;
+
+    // actor_c misses the generation change.
+    let packets = net
+        .propose(&actor_a, Reconfig::Leave(actor_e))
+        .map_err(|_| "Failed to propose leave")?;
+    run_packets_holding_back(&mut net, packets, |p| p.dest == actor_c);
+    assert_eq!(
+        net.proc(&actor_a)
+            .ok_or("No proc for actor_a")?
+            .membership
+            .gen,
+        1
+    );
+
+    let req_packet = net
+        .proc_mut(&actor_c)
+        .ok_or("No proc for actor_c")?
+        .exec_op(1u8)
+        .map_err(|_| "Failed to generate insert op")?
+        .into_iter()
+        .find(|p| p.dest == actor_a)
+        .ok_or("No request for actor_a")?;
+    let rejections = net.deliver_packet(req_packet);
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(
+        rejections[0].payload,
+        Payload::Rejected {
+            reason: RejectionReason::WrongGeneration { gen: 1 },
+            dot: Some(Dot::new(actor_c, 1)),
+        }
+    );
+
+    // actor_c learns it's behind and catches up on the generation it missed.
+    let reactions = net.deliver_packet(rejections[0].clone());
+    assert_eq!(reactions.len(), 1);
+    assert!(matches!(reactions[0].payload, Payload::AntiEntropy { .. }));
+    net.run_packets_to_completion(reactions);
+    assert_eq!(
+        net.proc(&actor_c)
+            .ok_or("No proc for actor_c")?
+            .membership
+            .gen,
+        1
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_lagging_proc_catches_up_automatically() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(4).setup::<TestDT>();
+    let (actor_a, actor_c) = (actors[0], actors[2]);
+    let c_proc = net.proc_mut(&actor_c).ok_or("No proc for actor_c")?;
+    c_proc.auto_catch_up = true;
+    c_proc.reputation.config.quarantine_threshold = Some(1);
+
+    // actor_c misses everything about the first op.
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(1u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    run_packets_holding_back(&mut net, packets, |p| p.dest == actor_c);
+    assert_eq!(
+        net.proc(&actor_c)
+            .ok_or("No proc for actor_c")?
+            .delivered
+            .get(&actor_a),
+        0
+    );
+
+    // actor_c notices it is behind when asked to sign the second op.
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(2u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    let req_packet = packets
+        .iter()
+        .find(|p| p.dest == actor_c)
+        .cloned()
+        .ok_or("No request for actor_c")?;
+    let others = packets.into_iter().filter(|p| p.dest != actor_c).collect();
+    let catch_up = net.deliver_packet(req_packet);
+    assert!(matches!(
+        catch_up[..],
+        [Packet {
+            payload: Payload::AntiEntropy { .. },
+            ..
+        }]
+    ));
+
+    // actor_a is not penalized for actor_c lagging behind.
+    let c_proc = net.proc(&actor_c).ok_or("No proc for actor_c")?;
+    assert_eq!(
+        c_proc.reputation.penalty(&actor_a, c_proc.packets_handled),
+        0
+    );
+    assert!(!c_proc
+        .reputation
+        .is_quarantined(&actor_a, c_proc.packets_handled));
+
+    net.run_packets_to_completion(catch_up);
+    net.run_packets_to_completion(others);
+
+    assert_eq!(
+        net.proc(&actor_c)
+            .ok_or("No proc for actor_c")?
+            .delivered
+            .get(&actor_a),
+        2
+    );
+    assert!(net.members_are_in_agreement());
+
+    Ok(())
+}
+
+#[test]
+#[allow(clippy::result_large_err)]
+fn test_anti_entropy_is_rate_limited_per_peer() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let (actor_a, actor_b, actor_c) = (actors[0], actors[1], actors[2]);
+
+    net.proc_mut(&actor_b)
+        .ok_or("No proc for actor_b")?
+        .rate_limiter
+        .limits = RateLimits {
+        anti_entropy: Some(RateLimit {
+            capacity: 1,
+            refill_interval: 3,
+        }),
+        ..Default::default()
+    };
+
+    let anti_entropy_from = |net: &TestNet, actor: &Actor| {
+        net.proc(actor)
+            .ok_or("No proc for actor")?
+            .anti_entropy(actor_b)
+            .map_err(|_| "Failed to generate anti-entropy packet")
+    };
+
+    let handle = |net: &mut TestNet, packet| {
+        net.proc_mut(&actor_b)
+            .ok_or("No proc for actor_b")
+            .map(|p| p.handle_packet(packet))
+    };
+
+    let packet = anti_entropy_from(&net, &actor_a)?;
+    assert!(handle(&mut net, packet.clone())?.is_ok());
+    assert!(matches!(
+        handle(&mut net, packet.clone())?,
+        Err(Error::RateLimited {
+            kind: LimitedPayload::AntiEntropy,
+            ..
+        })
+    ));
+
+    // Each peer has its own bucket.
+    let packet_from_c = anti_entropy_from(&net, &actor_c)?;
+    assert!(handle(&mut net, packet_from_c)?.is_ok());
+
+    // actor_b has handled 3 packets since actor_a's bucket was filled, it has regained a token.
+    assert!(handle(&mut net, packet.clone())?.is_ok());
+    assert!(handle(&mut net, packet)?.is_err());
+
+    // Packets that actor_a did not sign are refused before touching its bucket.
+    let mut tampered = anti_entropy_from(&net, &actor_a)?;
+    if let Payload::AntiEntropy { generation, .. } = &mut tampered.payload {
+        *generation += 1;
+    }
+    assert!(matches!(
+        handle(&mut net, tampered.clone())?,
+        Err(Error::Signature(_))
+    ));
+    let bucket = |net: &TestNet| {
+        net.proc(&actor_b)
+            .and_then(|p| {
+                p.rate_limiter
+                    .buckets
+                    .get(&(actor_a, LimitedPayload::AntiEntropy))
+                    .cloned()
+            })
+            .ok_or("No bucket for actor_a")
+    };
+    let penalty = |net: &TestNet| {
+        net.proc(&actor_b)
+            .map(|p| p.reputation.penalty(&actor_a, p.packets_handled))
+            .ok_or("No proc for actor_b")
+    };
+    let (bucket_before, penalty_before) = (bucket(&net)?, penalty(&net)?);
+    for _ in 0..3 {
+        assert!(handle(&mut net, tampered.clone())?.is_err());
+    }
+    assert_eq!(bucket(&net)?, bucket_before);
+    assert!(penalty(&net)? <= penalty_before);
+
+    Ok(())
+}
+
+#[test]
+fn test_errors_are_classified_by_severity() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let (actor_a, actor_b, actor_c) = (actors[0], actors[1], actors[2]);
+
+    let request_for = |packets: Vec<Packet<u8>>, dest: Actor| {
+        packets
+            .into_iter()
+            .find(|p| p.dest == dest)
+            .ok_or("No request for dest")
+    };
+
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(32u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    assert_eq!(net.deliver_packet(request_for(packets, actor_b)?).len(), 1);
+
+    // actor_a skips ahead to a second op, actor_c has not seen the first one yet.
+    let a_proc = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
+    a_proc.delivered = a_proc.received.clone();
+    let packets = a_proc
+        .exec_op(33u8)
+        .map_err(|_| "Failed to generate insert op")?;
+
+    let err = net
+        .proc_mut(&actor_c)
+        .ok_or("No proc for actor_c")?
+        .handle_packet(request_for(packets, actor_c)?)
+        .err()
+        .ok_or("Request from the future was accepted")?;
+    assert_eq!(err.severity(), Severity::Transient);
+    assert!(err.is_retryable());
+    assert!(!err.is_byzantine());
+    assert_eq!(err.offender(), None);
+
+    // actor_a asks actor_b to sign a different msg for the dot it already signed.
+    let a_proc = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
+    forget_sent_msgs(a_proc);
+    let packets = a_proc
+        .exec_op(34u8)
+        .map_err(|_| "Failed to generate insert op")?;
+
+    let err = net
+        .proc_mut(&actor_b)
+        .ok_or("No proc for actor_b")?
+        .handle_packet(request_for(packets, actor_b)?)
+        .err()
+        .ok_or("Conflicting request was accepted")?;
+    assert_eq!(err.severity(), Severity::Byzantine);
+    assert!(err.is_byzantine());
+    assert!(!err.is_retryable());
+    assert_eq!(err.offender(), Some(&actor_a));
+
+    Ok(())
+}
+
+#[test]
+fn test_membership_errors_are_classified_by_severity() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let (actor_a, actor_b) = (actors[0], actors[1]);
+    let (joining_x, joining_y) = (net.initialize_proc(), net.initialize_proc());
+
+    let vote_for = |packets: Vec<Packet<u8>>, dest: Actor| {
+        packets
+            .into_iter()
+            .find(|p| p.dest == dest)
+            .ok_or("No vote for dest")
+    };
+
+    let a_proc = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
+    let packets = a_proc
+        .request_membership(joining_x)
+        .map_err(|_| "Failed to propose join")?;
+    let first_vote = vote_for(packets, actor_b)?;
+    net.deliver_packet(first_vote.clone());
+
+    // actor_a forgets its vote and votes for a different join in the same generation.
+    let a_proc = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
+    a_proc.membership.votes.clear();
+    a_proc.membership.pending_gen = a_proc.membership.gen;
+    let packets = a_proc
+        .request_membership(joining_y)
+        .map_err(|_| "Failed to propose join")?;
+    let b_proc = net.proc_mut(&actor_b).ok_or("No proc for actor_b")?;
+    let err = b_proc
+        .handle_packet(vote_for(packets, actor_b)?)
+        .err()
+        .ok_or("Conflicting vote was accepted")?;
+    assert!(matches!(err, Error::Membership(_)));
+    assert_eq!(err.severity(), Severity::Byzantine);
+    assert_eq!(err.offender(), Some(&actor_a));
+    assert!(b_proc.reputation.penalty(&actor_a, b_proc.packets_handled) > 0);
+
+    // Once the generation has moved on, a vote for it is merely late.
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let (actor_a, actor_b) = (actors[0], actors[1]);
+    let joining = net.initialize_proc();
+    let packets = net
+        .propose(&actor_a, Reconfig::Join(joining))
+        .map_err(|_| "Failed to propose join")?;
+    let late_vote = vote_for(packets.clone(), actor_b)?;
+    net.run_packets_to_completion(packets);
+
+    let err = net
+        .proc_mut(&actor_b)
+        .ok_or("No proc for actor_b")?
+        .handle_packet(late_vote)
+        .err()
+        .ok_or("Late vote was accepted")?;
+    assert!(matches!(err, Error::Membership(_)));
+    assert_eq!(err.severity(), Severity::Transient);
+    assert_eq!(err.offender(), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_data_type_rejections_and_unexpected_acks_are_not_byzantine() -> Result<(), &'static str> {
+    let (_net, actors) = Scenario::genesis(1).setup::<TestDT>();
+    let actor_a = actors[0];
+
+    // The data type judges ops against our state, which may lag the source's.
+    let err: ValidationError<Actor, Sig, std::fmt::Error> =
+        ValidationError::DataTypeFailedValidation(std::fmt::Error);
+    assert_eq!(err.severity(), Severity::Transient);
+    assert_eq!(err.offender(), None);
+
+    let err: ValidationError<Actor, Sig, std::fmt::Error> =
+        ValidationError::DeliveredForPacketWeAreNotWaitingOn { from: actor_a };
+    assert_eq!(err.severity(), Severity::Transient);
+    assert_eq!(err.offender(), Some(&actor_a));
+
+    Ok(())
+}
+
+#[test]
+fn test_byzantine_peer_is_quarantined_and_proposed_for_removal() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let (actor_a, actor_b) = (actors[0], actors[1]);
+
+    let config = ReputationConfig {
+        quarantine_threshold: Some(20),
+        removal_threshold: Some(20),
+        ..Default::default()
+    };
+    net.proc_mut(&actor_b)
+        .ok_or("No proc for actor_b")?
+        .reputation = Reputation::new(config);
+
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(32u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    let req_packet = packets
+        .into_iter()
+        .find(|p| p.dest == actor_b)
+        .ok_or("No request for actor_b")?;
+    assert_eq!(net.deliver_packet(req_packet.clone()).len(), 1);
+
+    // actor_a equivocates, actor_b stops listening to it.
+    let a_proc = net.proc_mut(&actor_a).ok_or("No proc for actor_a")?;
+    forget_sent_msgs(a_proc);
+    let conflicting_packet = a_proc
+        .exec_op(33u8)
+        .map_err(|_| "Failed to generate insert op")?
+        .into_iter()
+        .find(|p| p.dest == actor_b)
+        .ok_or("No request for actor_b")?;
+    assert_eq!(net.deliver_packet(conflicting_packet), vec![]);
+
+    let b_proc = net.proc_mut(&actor_b).ok_or("No proc for actor_b")?;
+    assert!(matches!(
+        b_proc.handle_packet(req_packet),
+        Err(Error::Quarantined { peer }) if peer == actor_a
+    ));
+
+    let removal_votes = b_proc
+        .propose_removals()
+        .map_err(|_| "Failed to propose removals")?;
+    assert!(!removal_votes.is_empty());
+    assert!(removal_votes
+        .iter()
+        .all(|p| matches!(p.payload, Payload::Membership(_))));
+
+    // We only propose to remove a peer once.
+    assert_eq!(
+        b_proc
+            .propose_removals()
+            .map_err(|_| "Failed to propose removals")?,
+        vec![]
+    );
+
+    // Penalties decay as packets are handled.
+    let mut reputation = Reputation::new(ReputationConfig {
+        decay_interval: 2,
+        ..config
+    });
+    assert_eq!(reputation.penalize(actor_a, Severity::Byzantine, 0), 20);
+    assert!(reputation.is_quarantined(&actor_a, 1));
+    assert_eq!(reputation.penalty(&actor_a, 10), 15);
+    assert!(!reputation.is_quarantined(&actor_a, 10));
+    assert_eq!(reputation.penalize(actor_a, Severity::Transient, 10), 16);
+
+    Ok(())
+}
+
+#[test]
+fn test_partial_decay_is_kept_across_penalties() -> Result<(), &'static str> {
+    let (_net, actors) = Scenario::genesis(1).setup::<TestDT>();
+    let actor_a = actors[0];
+    let decay_interval = 4;
+    let mut reputation = Reputation::new(ReputationConfig {
+        decay_interval,
+        ..Default::default()
+    });
+
+    // A point is forgiven every decay_interval packets even though the peer is
+    // penalized again before a full interval passes since its last penalty.
+    let mut now = 0;
+    for penalties in 1..=12 {
+        let penalty = reputation.penalize(actor_a, Severity::Transient, now);
+        assert_eq!(penalty, penalties - now / decay_interval);
+        now += decay_interval - 1;
+    }
+
+    // Once penalties stop, the remaining points keep being forgiven on schedule.
+    let last = now - (decay_interval - 1);
+    let remaining = reputation.penalty(&actor_a, last);
+    assert_eq!(remaining, 12 - last / decay_interval);
+    let forgiven_at = (last / decay_interval + remaining) * decay_interval;
+    assert_eq!(reputation.penalty(&actor_a, forgiven_at - 1), 1);
+    assert_eq!(reputation.penalty(&actor_a, forgiven_at), 0);
+
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_metrics_are_exported_in_prometheus_format() -> Result<(), &'static str> {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let actor_a = actors[0];
+
+    let packets = net
+        .proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(32u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    net.run_packets_to_completion(packets);
+
+    let a_proc = net.proc(&actor_a).ok_or("No proc for actor_a")?;
+    assert_eq!(a_proc.metrics.commit_latency.count, 1);
+
+    let metrics = a_proc.encode_metrics();
+    assert!(metrics.contains("brb_packets_handled_total{kind=\"signed_validated\"} 3\n"));
+    assert!(metrics.contains("brb_pending_delivery 0\n"));
+    assert!(metrics.contains("brb_history_length 1\n"));
+    assert!(metrics.contains("brb_commit_latency_seconds_count 1\n"));
+
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|_| "Failed to bind")?;
+    let addr = listener.local_addr().map_err(|_| "No local addr")?;
+    let mut client = TcpStream::connect(addr).map_err(|_| "Failed to connect")?;
+    client
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .map_err(|_| "Failed to send request")?;
+    let (stream, _) = listener.accept().map_err(|_| "Failed to accept")?;
+    brb::metrics::serve_scrape(stream, &metrics).map_err(|_| "Failed to serve scrape")?;
+
+    let mut response = String::new();
+    client
+        .read_to_string(&mut response)
+        .map_err(|_| "Failed to read response")?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with(&metrics));
+
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_oversized_scrape_requests_are_refused() -> Result<(), &'static str> {
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|_| "Failed to bind")?;
+    let addr = listener.local_addr().map_err(|_| "No local addr")?;
+    let mut client = TcpStream::connect(addr).map_err(|_| "Failed to connect")?;
+    let header = format!(
+        "GET /metrics HTTP/1.1\r\nX-Padding: {}\r\n",
+        "a".repeat(brb::metrics::MAX_SCRAPE_REQUEST_LEN as usize)
+    );
+    client
+        .write_all(header.as_bytes())
+        .map_err(|_| "Failed to send request")?;
+    let (stream, _) = listener.accept().map_err(|_| "Failed to accept")?;
+
+    assert!(brb::metrics::serve_scrape(stream, "").is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_timed_msgs_are_bounded() -> Result<(), &'static str> {
+    use brb::metrics::{Metrics, MAX_TIMED_EXECS};
+
+    let (_, actors) = Scenario::genesis(1).setup::<TestDT>();
+    let actor_a = actors[0];
+
+    // none of these msgs are ever committed
+    let mut metrics = Metrics::default();
+    for counter in 1..=MAX_TIMED_EXECS as u64 + 1 {
+        metrics.record_exec(Dot::new(actor_a, counter));
+    }
+
+    assert_eq!(metrics.exec_started.len(), MAX_TIMED_EXECS);
+    assert!(!metrics.exec_started.contains_key(&Dot::new(actor_a, 1)));
+    assert!(metrics
+        .exec_started
+        .contains_key(&Dot::new(actor_a, MAX_TIMED_EXECS as u64 + 1)));
+
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_msgs_abandoned_by_a_generation_change_are_no_longer_timed() -> Result<(), &'static str> {
+    let (mut net, actors) = Scenario::genesis(3).setup::<TestDT>();
+    let (actor_a, actor_b) = (actors[0], actors[1]);
+
+    // actor_a's msg never reaches the other members.
+    net.proc_mut(&actor_a)
+        .ok_or("No proc for actor_a")?
+        .exec_op(32u8)
+        .map_err(|_| "Failed to generate insert op")?;
+    let a_proc = net.proc(&actor_a).ok_or("No proc for actor_a")?;
+    assert_eq!(a_proc.metrics.exec_started.len(), 1);
+
+    let actor_d = net.initialize_proc();
+    let packets = net
+        .propose(&actor_b, Reconfig::Join(actor_d))
+        .map_err(|_| "Failed to propose join")?;
+    net.run_packets_to_completion(packets);
+
+    let a_proc = net.proc(&actor_a).ok_or("No proc for actor_a")?;
+    assert_eq!(a_proc.membership.gen, 1);
+    assert!(a_proc.metrics.exec_started.is_empty());
+    assert_eq!(a_proc.metrics.commit_latency.count, 0);
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use brb::net::{Actor, DiagramFilter, DiagramFormat};
+
+use common::{bootstrap_net, TestDT, TestNet};
+
+fn net_with_one_op() -> (TestNet, Vec<Actor>) {
+    let (mut net, actors) = bootstrap_net::<TestDT>(3);
+
+    let packets = net.exec_op(&actors[0], 7).unwrap();
+    net.run_packets_to_completion(packets);
+    (net, actors)
+}
+
+#[test]
+fn test_diagrams_label_every_delivered_packet() {
+    let (net, _actors) = net_with_one_op();
+    let n_packets = net.delivered_packets.len();
+
+    let msc = net.diagram(DiagramFormat::Msc, &Default::default());
+    assert!(msc.starts_with("msc {\n  hscale = \"2\";\n  1,2,3;\n"));
+    assert_eq!(msc.matches(" [ label=").count(), n_packets);
+    assert!(msc.contains("  1->2 [ label=\"request_validation 1.1 7\"];\n"));
+    assert!(msc.contains("  2->1 [ label=\"signed_validated 1.1\"];\n"));
+    assert!(msc.contains("  1->3 [ label=\"proof_of_agreement 1.1 (3 sigs)\"];\n"));
+    assert!(msc.contains("  3->1 [ label=\"delivered 1.1\"];\n"));
+
+    let mermaid = net.diagram(DiagramFormat::Mermaid, &Default::default());
+    assert!(mermaid.starts_with("sequenceDiagram\n  participant p1 as 1 (i:"));
+    assert_eq!(mermaid.matches("->>").count(), n_packets);
+    assert!(mermaid.contains("  p1->>p2: request_validation 1.1 7\n"));
+
+    let plantuml = net.diagram(DiagramFormat::PlantUml, &Default::default());
+    assert!(plantuml.starts_with("@startuml\nparticipant \"1 (i:"));
+    assert!(plantuml.ends_with("@enduml\n"));
+    assert_eq!(plantuml.matches(" -> ").count(), n_packets);
+    assert!(plantuml.contains("p2 -> p1 : signed_validated 1.1\n"));
+}
+
+#[test]
+fn test_diagrams_only_draw_filtered_packets() {
+    let (net, actors) = net_with_one_op();
+
+    let filter = DiagramFilter {
+        kinds: Some(vec!["proof_of_agreement"].into_iter().collect()),
+        ..Default::default()
+    };
+    let proofs = net.diagram(DiagramFormat::PlantUml, &filter);
+    assert_eq!(proofs.matches(" -> ").count(), 3);
+    assert_eq!(proofs.matches("proof_of_agreement").count(), 3);
+
+    let filter = DiagramFilter {
+        actors: Some(vec![actors[2]].into_iter().collect()),
+        ..Default::default()
+    };
+    let third = net.diagram(DiagramFormat::Mermaid, &filter);
+    assert!(third
+        .lines()
+        .filter(|l| l.contains("->>"))
+        .all(|l| l.contains("p3")));
+    assert_eq!(third.matches("->>").count(), 4);
+
+    let filter = DiagramFilter {
+        steps: Some(0..2),
+        ..Default::default()
+    };
+    let first = net.diagram(DiagramFormat::Msc, &filter);
+    assert_eq!(first.matches(" [ label=").count(), 2);
+    assert_eq!(first.matches("request_validation").count(), 2);
+}
+
+#[test]
+fn test_diagrams_are_written_to_any_writer() {
+    let (net, _actors) = net_with_one_op();
+
+    let mut bytes = vec![];
+    net.write_diagram(&mut bytes, DiagramFormat::Mermaid, &Default::default())
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8(bytes).unwrap(),
+        net.diagram(DiagramFormat::Mermaid, &Default::default())
+    );
+}
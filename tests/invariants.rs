@@ -0,0 +1,109 @@
+mod common;
+
+use std::collections::BTreeSet;
+
+use brb::{
+    deterministic_brb::Msg,
+    net::{
+        byzantine::Equivocate, faults::env_seed, CrossPartition, FaultModel, Packet, Partition,
+        RandomScheduler,
+    },
+    DeliveryOutcome,
+};
+use crdts::{CmRDT, Dot};
+
+use common::TestNet;
+
+fn no_check(_net: &TestNet, _pending: &[Packet<u8>]) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_invariants_hold_for_concurrent_ops_under_a_random_schedule() {
+    let mut net = TestNet::with_invariants();
+    net.scheduler = Box::new(RandomScheduler::from_env());
+    let actors = net.initialize_members(4);
+
+    for round in 0..3 {
+        let mut packets = vec![];
+        for (i, actor) in actors.iter().enumerate() {
+            packets.extend(net.exec_op(actor, round * 4 + i as u8).unwrap());
+        }
+        net.run_packets_to_completion(packets);
+    }
+
+    let seeds = net.seeds();
+    assert_eq!(net.violation, None, "{}", seeds);
+    assert!(net.members_are_in_agreement(), "{}", seeds);
+}
+
+#[test]
+fn test_invariants_hold_for_honest_procs_despite_equivocation() {
+    let mut net = TestNet::with_invariants();
+    let actors = net.initialize_members(4);
+    let byzantine = actors[3];
+    net.make_byzantine(byzantine, Equivocate { alternative: 2 });
+
+    let packets = net.exec_op(&byzantine, 1).unwrap();
+    net.run_packets_to_completion(packets);
+    let packets = net.exec_op(&actors[0], 3).unwrap();
+    net.run_packets_to_completion(packets);
+
+    assert_eq!(net.violation, None);
+    assert!(net.members_are_in_agreement());
+}
+
+#[test]
+fn test_totality_violation_is_reported_when_a_member_is_cut_off() {
+    let mut net = TestNet::with_invariants();
+    net.faults = Some(FaultModel::from_env(Default::default()));
+    let actors = net.initialize_members(4);
+    let isolated: BTreeSet<_> = actors[3..].iter().copied().collect();
+    let majority: BTreeSet<_> = actors[..3].iter().copied().collect();
+    net.partition(Partition::new(
+        vec![majority, isolated],
+        CrossPartition::Drop,
+    ));
+
+    let packets = net.exec_op(&actors[0], 7).unwrap();
+    let violation = net.run_packets_checked(packets, no_check).unwrap_err();
+
+    assert!(violation.reason.starts_with("Totality"));
+    assert_eq!(violation.step, net.n_packets);
+    assert_eq!(violation.seeds.faults, Some(env_seed()));
+    assert_eq!(net.violation, Some(violation));
+}
+
+#[test]
+fn test_first_step_delivering_without_a_valid_proof_is_reported() {
+    let mut net = TestNet::with_invariants();
+    let actors = net.initialize_members(3);
+
+    let packets = net.exec_op(&actors[0], 1).unwrap();
+    assert_eq!(net.run_packets_checked(packets, no_check), Ok(()));
+
+    // simulate a bug in a proc delivering a msg under a proof meant for another msg.
+    let proc = net.proc_mut(&actors[1]).unwrap();
+    let (_msg, proof, _outcome) = proc.history_from_source[&actors[0]][0].clone();
+    let dot = Dot::new(actors[0], 2);
+    let msg = Msg {
+        gen: proc.membership.gen,
+        op: 2,
+        dot,
+    };
+    proc.received.apply(dot);
+    proc.delivered.apply(dot);
+    proc.history_from_source.get_mut(&actors[0]).unwrap().push((
+        msg,
+        proof,
+        DeliveryOutcome::Applied,
+    ));
+
+    let step = net.n_packets + 1;
+    let packets = net.exec_op(&actors[2], 3).unwrap();
+    let violation = net.run_packets_checked(packets, no_check).unwrap_err();
+
+    assert!(violation.reason.starts_with("Validity"));
+    assert_eq!(violation.step, step);
+    assert_eq!(net.n_packets, step);
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use brb::{
+    net::{Actor, Net},
+    BRBDataType, BRBKvStore, KvOp,
+};
+
+use common::bootstrap_net;
+
+type KvStore = BRBKvStore<Actor, String, String>;
+
+fn exec(
+    net: &mut Net<KvStore>,
+    actor: &Actor,
+    op: KvOp<Actor, String, String>,
+) -> Result<(), &'static str> {
+    let packets = net
+        .proc_mut(actor)
+        .ok_or("No proc for actor")?
+        .exec_op(op)
+        .map_err(|_| "Failed to generate op")?;
+    net.run_packets_to_completion(packets);
+    Ok(())
+}
+
+#[test]
+fn test_put_and_delete_are_replicated() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<KvStore>(3);
+    let actor_a = actors[0];
+
+    let kv = &net.proc(&actor_a).ok_or("No proc for actor_a")?.dt;
+    let op = kv.put("log_level".to_string(), "debug".to_string());
+    exec(&mut net, &actor_a, op)?;
+
+    let kv = &net.proc(&actor_a).ok_or("No proc for actor_a")?.dt;
+    let op = kv.put("log_level".to_string(), "info".to_string());
+    exec(&mut net, &actor_a, op)?;
+
+    let kv = &net.proc(&actor_a).ok_or("No proc for actor_a")?.dt;
+    let op = kv.put("peers".to_string(), "3".to_string());
+    exec(&mut net, &actor_a, op)?;
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+    for proc in net.procs.iter() {
+        assert_eq!(proc.dt.len(), 2);
+        assert_eq!(
+            proc.dt.get(&"log_level".to_string()),
+            Some(&"info".to_string())
+        );
+        assert_eq!(proc.dt.owner(&"log_level".to_string()), Some(&actor_a));
+    }
+
+    let kv = &net.proc(&actor_a).ok_or("No proc for actor_a")?.dt;
+    let op = kv.delete("log_level".to_string());
+    exec(&mut net, &actor_a, op)?;
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    for proc in net.procs.iter() {
+        assert_eq!(
+            proc.dt.iter().collect::<Vec<_>>(),
+            vec![(&"peers".to_string(), &"3".to_string())]
+        );
+        assert!(!proc.dt.contains_key(&"log_level".to_string()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_only_the_owner_may_write_a_key() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<KvStore>(3);
+    let actor_a = actors[0];
+    let actor_b = actors[1];
+
+    let kv = &net.proc(&actor_a).ok_or("No proc for actor_a")?.dt;
+    let op = kv.put("leader".to_string(), "a".to_string());
+    exec(&mut net, &actor_a, op)?;
+
+    let proc_b = net.proc_mut(&actor_b).ok_or("No proc for actor_b")?;
+    let op = proc_b.dt.put("leader".to_string(), "b".to_string());
+    assert!(proc_b.exec_op(op).is_err());
+    let op = proc_b.dt.delete("leader".to_string());
+    assert!(proc_b.exec_op(op).is_err());
+
+    // actor_b can't forge a write in actor_a's name either
+    let op = KvOp::Put {
+        key: "leader".to_string(),
+        value: "b".to_string(),
+        actor: actor_a,
+        version: 2,
+    };
+    assert!(proc_b.dt.validate(&actor_b, &op).is_err());
+
+    // once deleted, the key is free to be claimed by anyone
+    let kv = &net.proc(&actor_a).ok_or("No proc for actor_a")?.dt;
+    let op = kv.delete("leader".to_string());
+    exec(&mut net, &actor_a, op)?;
+
+    let kv = &net.proc(&actor_b).ok_or("No proc for actor_b")?.dt;
+    let op = kv.put("leader".to_string(), "b".to_string());
+    exec(&mut net, &actor_b, op)?;
+
+    assert_eq!(net.count_invalid_packets(), 0);
+    for proc in net.procs.iter() {
+        assert_eq!(proc.dt.owner(&"leader".to_string()), Some(&actor_b));
+        assert_eq!(
+            proc.dt
+                .entry(&"leader".to_string())
+                .ok_or("No entry for leader")?
+                .version,
+            3
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_claims_of_a_key_converge() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<KvStore>(3);
+
+    let mut packets = Vec::new();
+    let mut claims = Vec::new();
+    for actor in actors.iter() {
+        let proc = net.proc_mut(actor).ok_or("No proc for actor")?;
+        let op = proc.dt.put("leader".to_string(), actor.to_string());
+        claims.push((op.version(), *actor));
+        packets.extend(proc.exec_op(op).map_err(|_| "Failed to generate op")?);
+    }
+    net.run_packets_to_completion(packets);
+    assert_eq!(net.count_invalid_packets(), 0);
+    assert!(net.members_are_in_agreement());
+
+    // Every claim was made on the same version, the claim with the highest
+    // (version, actor) wins on every proc.
+    let (version, winner) = claims.into_iter().max().ok_or("No claims")?;
+    for proc in net.procs.iter() {
+        assert_eq!(proc.dt.len(), 1);
+        let entry = proc
+            .dt
+            .entry(&"leader".to_string())
+            .ok_or("No entry for leader")?;
+        assert_eq!((entry.version, entry.owner), (version, winner));
+        assert_eq!(
+            proc.dt.get(&"leader".to_string()),
+            Some(&winner.to_string())
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use std::collections::BTreeSet;
+
+use brb::net::{CrossPartition, Latency, LatencyModel, Partition};
+
+use common::TestNet;
+
+#[test]
+fn test_op_timings_follow_link_latency() {
+    let mut net = TestNet::with_latency(LatencyModel::new(0, Latency::Fixed(10)));
+    let actors = net.initialize_members(4);
+
+    let packets = net.exec_op(&actors[0], 1).unwrap();
+    net.run_packets_to_completion(packets);
+
+    assert!(net.members_are_in_agreement());
+    // request, signature, proof and delivered acknowledgement each take a hop.
+    assert_eq!(net.clock, 40);
+    let timing = &net.latency.as_ref().unwrap().ops[&(actors[0], 1)];
+    assert_eq!(timing.sent, 0);
+    assert_eq!(timing.time_to_proof(), Some(20));
+    assert_eq!(timing.time_to_supermajority(), Some(30));
+    assert_eq!(timing.delivered_by.len(), 4);
+
+    let report = net.latency_report().unwrap();
+    assert_eq!(report.ops, 1);
+    assert_eq!(report.time_to_proof.map(|s| s.max), Some(20));
+    assert_eq!(report.time_to_supermajority.map(|s| s.mean), Some(30));
+}
+
+#[test]
+fn test_packets_are_delivered_in_arrival_order() {
+    let mut net = TestNet::new();
+    let actors = net.initialize_members(4);
+    let slow = actors[3];
+    net.latency = Some(LatencyModel::new(0, Latency::Fixed(10)).with_link(
+        actors[0],
+        slow,
+        Latency::Fixed(100),
+    ));
+
+    let packets = net.exec_op(&actors[0], 1).unwrap();
+    net.run_packets_to_completion(packets);
+
+    assert!(net.members_are_in_agreement());
+    // the fast procs reach a supermajority without waiting on the slow link.
+    let timing = &net.latency.as_ref().unwrap().ops[&(actors[0], 1)];
+    assert_eq!(timing.time_to_proof(), Some(20));
+    assert_eq!(timing.time_to_supermajority(), Some(30));
+    assert_eq!(net.clock, 130);
+
+    let first_to_slow = net
+        .delivered_packets
+        .iter()
+        .position(|p| p.dest == slow)
+        .unwrap();
+    assert!(net.delivered_packets[..first_to_slow]
+        .iter()
+        .any(|p| p.payload.is_proof_of_agreement()));
+}
+
+#[test]
+fn test_op_timings_fall_within_link_latency_bounds() {
+    let mut net =
+        TestNet::with_latency(LatencyModel::from_env(Latency::Uniform { min: 5, max: 15 }));
+    let actors = net.initialize_members(4);
+
+    for round in 0..3 {
+        let mut packets = vec![];
+        for (i, actor) in actors.iter().enumerate() {
+            packets.extend(net.exec_op(actor, round * 4 + i as u8).unwrap());
+        }
+        net.run_packets_to_completion(packets);
+    }
+
+    let seeds = net.seeds();
+    assert!(net.members_are_in_agreement(), "{}", seeds);
+    let report = net.latency_report().unwrap();
+    assert_eq!(report.ops, 12, "{}", seeds);
+    let to_proof = report.time_to_proof.unwrap();
+    assert_eq!(to_proof.count, 12, "{}", seeds);
+    assert!(to_proof.min >= 10 && to_proof.max <= 30, "{}", seeds);
+    let to_supermajority = report.time_to_supermajority.unwrap();
+    assert_eq!(to_supermajority.count, 12, "{}", seeds);
+    assert!(
+        to_supermajority.min >= 15 && to_supermajority.max <= 45,
+        "{}",
+        seeds
+    );
+}
+
+#[test]
+fn test_deliveries_replayed_from_the_reorder_buffer_are_timed() -> Result<(), &'static str> {
+    let mut net = TestNet::with_latency(LatencyModel::new(0, Latency::Fixed(10)));
+    let actors = net.initialize_members(4);
+    let slow = actors[3];
+
+    // the other procs form a quorum for both ops while packets to the slow proc are held
+    let majority: BTreeSet<_> = actors[..3].iter().copied().collect();
+    let minority: BTreeSet<_> = vec![slow].into_iter().collect();
+    net.partition(Partition::new(
+        vec![majority, minority],
+        CrossPartition::Queue,
+    ));
+    for op in 1..=2 {
+        let packets = net.exec_op(&actors[0], op).map_err(|_| "op refused")?;
+        net.run_packets_to_completion(packets);
+    }
+
+    // the proof of the second op reaches the slow proc first and waits for the first op
+    net.partition = None;
+    let mut held = std::mem::take(&mut net.partitioned_packets);
+    held.reverse();
+    net.run_packets_to_completion(held);
+
+    assert!(net.members_are_in_agreement());
+    let latency = net.latency.as_ref().ok_or("no latency model")?;
+    for counter in 1..=2 {
+        let timing = &latency.ops[&(actors[0], counter)];
+        assert!(timing.delivered_by.contains(&slow));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_clock_does_not_advance_without_a_latency_model() {
+    let mut net = TestNet::new();
+    let actors = net.initialize_members(3);
+
+    let packets = net.exec_op(&actors[0], 1).unwrap();
+    net.run_packets_to_completion(packets);
+
+    assert_eq!(net.clock, 0);
+    assert_eq!(net.latency_report(), None);
+}
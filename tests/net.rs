@@ -0,0 +1,283 @@
+mod common;
+
+use std::collections::BTreeSet;
+
+use brb::net::{
+    explore, CrossPartition, ExploreBounds, Fault, FaultModel, Latency, LatencyModel, LinkFaults,
+    Packet, Partition, RandomScheduler, ReplayScheduler,
+};
+
+use common::TestNet;
+
+#[test]
+fn test_fault_model_is_reproducible_from_its_seed() {
+    let mut net = TestNet::new();
+    let actors = net.initialize_members(2);
+    let faults = LinkFaults {
+        drop: 0.2,
+        duplicate: 0.2,
+        reorder: 0.2,
+    };
+    let silent = LinkFaults {
+        drop: 1.0,
+        ..Default::default()
+    };
+
+    let mut model = FaultModel::from_env(faults).with_link(actors[1], actors[0], silent);
+    let mut replay = FaultModel::new(model.seed, faults).with_link(actors[1], actors[0], silent);
+
+    let seed = model.seed;
+    for queued in 0..100 {
+        let fault = model.decide(&actors[0], &actors[1], queued);
+        assert_eq!(
+            fault,
+            replay.decide(&actors[0], &actors[1], queued),
+            "seed {}",
+            seed
+        );
+        assert_eq!(
+            model.decide(&actors[1], &actors[0], queued),
+            Fault::Drop,
+            "seed {}",
+            seed
+        );
+        assert_eq!(
+            replay.decide(&actors[1], &actors[0], queued),
+            Fault::Drop,
+            "seed {}",
+            seed
+        );
+    }
+    assert_eq!(model.stats, replay.stats, "seed {}", seed);
+}
+
+#[test]
+fn test_brb_agrees_despite_duplicated_and_reordered_packets() {
+    let mut net = TestNet::with_faults(FaultModel::from_env(LinkFaults {
+        drop: 0.0,
+        duplicate: 0.2,
+        reorder: 0.3,
+    }));
+    let actors = net.initialize_members(4);
+
+    for (op, actor) in actors.iter().enumerate() {
+        let packets = net.proc_mut(actor).unwrap().exec_op(op as u8).unwrap();
+        net.run_packets_to_completion(packets);
+    }
+
+    let seeds = net.seeds();
+    assert!(net.members_are_in_agreement(), "{}", seeds);
+    for proc in net.procs.iter() {
+        assert_eq!(proc.dt.set, (0..actors.len() as u8).collect(), "{}", seeds);
+    }
+}
+
+#[test]
+fn test_reordered_packets_are_queued_behind_their_position() {
+    let mut net = TestNet::with_scheduler(ReplayScheduler::new(vec![1, 1]));
+    let actors = net.initialize_members(4);
+    net.faults = Some(FaultModel::scripted(vec![Fault::Reorder(1)]));
+
+    let packets = net.proc_mut(&actors[0]).unwrap().exec_op(1).unwrap();
+    assert_eq!(packets.len(), 3);
+    net.run_packets_to_completion(packets.clone());
+
+    // the packet picked at 1 goes behind the packet at 2, which is delivered first
+    assert_eq!(net.faults.as_ref().unwrap().stats.reordered, 1);
+    assert_eq!(net.delivered_packets[0], packets[2]);
+    assert!(net.members_are_in_agreement());
+}
+
+#[test]
+fn test_packets_are_not_reordered_when_latency_orders_delivery() {
+    let mut net = TestNet::with_latency(LatencyModel::new(0, Latency::Fixed(10)));
+    let actors = net.initialize_members(4);
+    net.faults = Some(FaultModel::scripted(vec![Fault::Reorder(1)]));
+
+    let packets = net.proc_mut(&actors[0]).unwrap().exec_op(1).unwrap();
+    net.run_packets_to_completion(packets.clone());
+
+    assert_eq!(net.faults.as_ref().unwrap().stats.reordered, 0);
+    assert_eq!(net.delivered_packets[0], packets[0]);
+    assert!(net.members_are_in_agreement());
+}
+
+#[test]
+fn test_dropped_packets_are_recovered_through_anti_entropy() {
+    let mut net = TestNet::new();
+    let actors = net.initialize_members(4);
+    let (actor_a, actor_b) = (actors[0], actors[1]);
+
+    // everything actor_a sends to actor_b is lost, the other 3 procs form a quorum.
+    let silent = LinkFaults {
+        drop: 1.0,
+        ..Default::default()
+    };
+    net.faults = Some(FaultModel::from_env(Default::default()).with_link(actor_a, actor_b, silent));
+
+    let packets = net.proc_mut(&actor_a).unwrap().exec_op(7).unwrap();
+    net.run_packets_to_completion(packets);
+
+    let seeds = net.seeds();
+    assert!(net.faults.as_ref().unwrap().stats.dropped > 0, "{}", seeds);
+    assert_eq!(
+        net.proc(&actor_a).unwrap().delivered.get(&actor_a),
+        1,
+        "{}",
+        seeds
+    );
+    assert_eq!(
+        net.proc(&actor_b).unwrap().delivered.get(&actor_a),
+        0,
+        "{}",
+        seeds
+    );
+
+    net.faults = None;
+    net.anti_entropy();
+
+    assert!(net.members_are_in_agreement(), "{}", seeds);
+    assert!(net.proc(&actor_b).unwrap().dt.set.contains(&7), "{}", seeds);
+}
+
+#[test]
+fn test_partitioned_minority_catches_up_after_healing() {
+    let mut net = TestNet::new();
+    let actors = net.initialize_members(4);
+    let (actor_a, actor_d) = (actors[0], actors[3]);
+
+    let majority: BTreeSet<_> = actors[..3].iter().copied().collect();
+    let minority: BTreeSet<_> = vec![actor_d].into_iter().collect();
+    net.partition(Partition::new(
+        vec![majority, minority],
+        CrossPartition::Drop,
+    ));
+
+    // the majority can still form a quorum, the minority can not
+    let packets = net.proc_mut(&actor_a).unwrap().exec_op(1).unwrap();
+    net.run_packets_to_completion(packets);
+    let packets = net.proc_mut(&actor_d).unwrap().exec_op(2).unwrap();
+    net.run_packets_to_completion(packets);
+
+    for actor in actors[..3].iter() {
+        let proc = net.proc(actor).unwrap();
+        assert_eq!(proc.dt.set, vec![1].into_iter().collect());
+    }
+    assert!(net.proc(&actor_d).unwrap().dt.set.is_empty());
+
+    net.heal();
+    net.anti_entropy();
+
+    assert!(net.members_are_in_agreement());
+    assert_eq!(
+        net.proc(&actor_d).unwrap().dt.set,
+        vec![1].into_iter().collect()
+    );
+}
+
+#[test]
+fn test_queued_packets_are_delivered_when_partition_heals() {
+    let mut net = TestNet::new();
+    let actors = net.initialize_members(4);
+    let actor_a = actors[0];
+
+    // neither side of an even split can form a quorum until the partition heals
+    let left: BTreeSet<_> = actors[..2].iter().copied().collect();
+    let right: BTreeSet<_> = actors[2..].iter().copied().collect();
+    net.partition(
+        Partition::new(vec![left, right], CrossPartition::Queue).heal_at(net.n_packets + 2),
+    );
+
+    let packets = net.proc_mut(&actor_a).unwrap().exec_op(1).unwrap();
+    net.run_packets_to_completion(packets);
+
+    assert!(net.partition.is_none());
+    assert!(net.partitioned_packets.is_empty());
+    assert!(net.members_are_in_agreement());
+    for proc in net.procs.iter() {
+        assert_eq!(proc.dt.set, vec![1].into_iter().collect());
+    }
+}
+
+#[test]
+fn test_brb_agrees_under_a_random_schedule() {
+    let mut net = TestNet::with_scheduler(RandomScheduler::from_env());
+    let actors = net.initialize_members(4);
+
+    for round in 0..3 {
+        let mut packets = vec![];
+        for (i, actor) in actors.iter().enumerate() {
+            packets.extend(net.exec_op(actor, round * 4 + i as u8).unwrap());
+        }
+        net.run_packets_to_completion(packets);
+    }
+
+    let seeds = net.seeds();
+    assert!(net.members_are_in_agreement(), "{}", seeds);
+    assert_eq!(net.schedule.len() as u64, net.n_packets, "{}", seeds);
+    for proc in net.procs.iter() {
+        assert_eq!(proc.dt.set, (0..12).collect(), "{}", seeds);
+    }
+}
+
+// Ops are sent by actors in key order, so replays see the same packets in the same order.
+fn concurrent_ops() -> (TestNet, Vec<Packet<u8>>) {
+    let mut net = TestNet::new();
+    let mut actors = net.initialize_members(3);
+    actors.sort();
+    let mut packets = net.exec_op(&actors[0], 1).unwrap();
+    packets.extend(net.exec_op(&actors[1], 2).unwrap());
+    (net, packets)
+}
+
+#[test]
+fn test_explored_schedules_all_reach_agreement() {
+    let bounds = ExploreBounds {
+        depth: 6,
+        schedules: 500,
+    };
+    let runs = explore(
+        bounds,
+        concurrent_ops,
+        |net: &TestNet, pending: &[Packet<u8>]| {
+            if net.count_invalid_packets() > 0 {
+                return Err("a packet was rejected".to_string());
+            }
+            if pending.is_empty() && !net.members_are_in_agreement() {
+                return Err("members disagree".to_string());
+            }
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    assert!(runs > 1);
+    assert!(runs <= bounds.schedules);
+}
+
+#[test]
+fn test_explorer_finds_order_dependent_violation_and_replays_it() {
+    let bounds = ExploreBounds {
+        depth: 3,
+        schedules: 1000,
+    };
+    let procs_apply_1_before_2 = |net: &TestNet, _pending: &[Packet<u8>]| match net
+        .procs
+        .iter()
+        .find(|p| p.dt.set.contains(&2) && !p.dt.set.contains(&1))
+    {
+        Some(proc) => Err(format!("{} applied 2 before 1", proc.actor())),
+        None => Ok(()),
+    };
+
+    let violation = explore(bounds, concurrent_ops, procs_apply_1_before_2).unwrap_err();
+    assert_eq!(violation.schedule.len() as u64, violation.step);
+
+    let (mut net, packets) = concurrent_ops();
+    net.scheduler = Box::new(ReplayScheduler::new(violation.schedule.clone()));
+    let replayed = net
+        .run_packets_checked(packets, procs_apply_1_before_2)
+        .unwrap_err();
+    assert_eq!(replayed.step, violation.step);
+    assert_eq!(replayed.schedule, violation.schedule);
+}
@@ -0,0 +1,71 @@
+#![cfg(feature = "proptest")]
+
+mod common;
+
+use brb::net::{
+    scenario::{scenario, ScenarioBounds},
+    Event, Fault, Scenario,
+};
+use proptest::prelude::*;
+
+use common::TestDT;
+
+#[test]
+fn test_scenario_runs_rounds_of_concurrent_ops() -> Result<(), &'static str> {
+    let scenario = Scenario {
+        n_procs: 4,
+        events: vec![
+            Event::Ops(vec![(0, 1), (1, 2), (2, 3), (3, 4)]),
+            Event::Ops(vec![(0, 5), (0, 6)]),
+        ],
+        schedule: vec![3, 1, 4, 1, 5, 9, 2, 6],
+        faults: vec![
+            Fault::Deliver,
+            Fault::Duplicate,
+            Fault::Reorder(2),
+            Fault::Drop,
+        ],
+    };
+
+    let outcome = scenario.run::<TestDT>();
+    let net = outcome.net;
+
+    assert!(outcome.refusals.is_empty());
+
+    assert_eq!(net.violation, None);
+    assert!(net.members_are_in_agreement());
+    assert_eq!(net.members().len(), 4);
+    for proc in net.procs.iter() {
+        assert_eq!(proc.dt.set, vec![1, 2, 3, 4, 5].into_iter().collect());
+    }
+
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn prop_honest_members_agree_despite_faults(
+        scenario in scenario(ScenarioBounds { membership: false, ..Default::default() }, any::<u8>())
+    ) {
+        let outcome = scenario.run::<TestDT>();
+        let net = outcome.net;
+
+        prop_assert!(outcome.refusals.iter().all(|r| r.error.is_retryable()), "{:?}", outcome.refusals);
+
+        prop_assert_eq!(&net.violation, &None);
+        prop_assert!(net.members_are_in_agreement());
+    }
+
+    #[test]
+    fn prop_honest_members_agree_through_membership_changes(
+        scenario in scenario(ScenarioBounds { drops: false, ..Default::default() }, any::<u8>())
+    ) {
+        let outcome = scenario.run::<TestDT>();
+        let net = outcome.net;
+
+        prop_assert!(outcome.refusals.iter().all(|r| r.error.is_retryable()), "{:?}", outcome.refusals);
+
+        prop_assert_eq!(&net.violation, &None);
+        prop_assert!(net.members_are_in_agreement());
+    }
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use brb::net::{FaultModel, LinkFaults, RandomScheduler, Step, Trace};
+use brb::{RateLimit, RateLimits};
+
+use common::{TestDT, TestNet};
+
+/// Records a run of concurrent ops under a random schedule with duplicated and reordered packets.
+fn record_run() -> TestNet {
+    let mut net = TestNet::with_faults(FaultModel::from_env(LinkFaults {
+        drop: 0.0,
+        duplicate: 0.1,
+        reorder: 0.2,
+    }));
+    net.scheduler = Box::new(RandomScheduler::from_env());
+    let actors = net.initialize_members(4);
+
+    net.record_trace();
+    let mut packets = vec![];
+    for (op, actor) in actors.iter().enumerate() {
+        packets.extend(net.exec_op(actor, op as u8).unwrap());
+    }
+    // a second op while the first is pending is refused
+    assert!(net.exec_op(&actors[0], 100).is_err());
+    net.run_packets_to_completion(packets);
+    net
+}
+
+#[test]
+fn test_trace_saved_to_a_file_replays_against_fresh_procs() -> Result<(), &'static str> {
+    let net = record_run();
+    let seeds = net.seeds();
+    let trace = net.trace.as_ref().ok_or("trace not recorded")?;
+    let faults = trace.faults.as_ref().ok_or("fault model not recorded")?;
+    assert_eq!(Some(faults.seed), seeds.faults);
+
+    let path = std::env::temp_dir().join(format!("brb-trace-{}.bin", rand::random::<u64>()));
+    trace.save(&path).map_err(|_| "failed to save trace")?;
+    let loaded: Trace<u8> = Trace::load(&path).map_err(|_| "failed to load trace")?;
+    std::fs::remove_file(&path).map_err(|_| "failed to remove trace")?;
+    assert_eq!(loaded.steps, trace.steps);
+    assert_eq!(loaded.faults.as_ref(), Some(faults));
+
+    let replayed: TestNet = loaded
+        .replay()
+        .unwrap_or_else(|divergence| panic!("replay diverged: {:?}, {}", divergence, seeds));
+
+    assert_eq!(replayed.actors(), net.actors(), "{}", seeds);
+    assert_eq!(
+        replayed.delivered_packets, net.delivered_packets,
+        "{}",
+        seeds
+    );
+    for proc in net.procs.iter() {
+        let replayed_proc = replayed.proc(&proc.actor()).ok_or("proc not replayed")?;
+        assert_eq!(
+            replayed_proc.history_from_source, proc.history_from_source,
+            "{}",
+            seeds
+        );
+        assert_eq!(replayed_proc.dt.set, proc.dt.set, "{}", seeds);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_refused_ops_are_recorded_and_replayed() -> Result<(), &'static str> {
+    let net = record_run();
+    let seeds = net.seeds();
+    let mut trace = net.trace.ok_or("trace not recorded")?;
+
+    let step = trace
+        .steps
+        .iter()
+        .position(|step| step.refused().is_some())
+        .ok_or("refused op not recorded")?;
+    assert!(trace.steps[step].sent().is_empty(), "{}", seeds);
+    if let Err(divergence) = trace.replay::<TestDT>() {
+        panic!("replay diverged: {:?}, {}", divergence, seeds);
+    }
+
+    if let Step::ExecOp { refused, .. } = &mut trace.steps[step] {
+        *refused = None;
+    }
+    let divergence = trace
+        .replay::<TestDT>()
+        .err()
+        .ok_or("replay accepted an op it refused")?;
+
+    assert_eq!(divergence.step, step, "{}", seeds);
+    assert_eq!(divergence.expected_refusal, None, "{}", seeds);
+    assert!(divergence.actual_refusal.is_some(), "{}", seeds);
+    Ok(())
+}
+
+#[test]
+fn test_proc_config_is_replayed() -> Result<(), &'static str> {
+    let mut net = TestNet::new();
+    let actors = net.initialize_members(3);
+    let limits = RateLimits {
+        anti_entropy: Some(RateLimit {
+            capacity: 1,
+            refill_interval: 10,
+        }),
+        ..RateLimits::default()
+    };
+    for proc in net.procs.iter_mut() {
+        proc.reorder_buffer_limit = 2;
+        proc.generation_lookahead = 3;
+        proc.auto_catch_up = false;
+        proc.rate_limiter.limits = limits;
+    }
+    net.send_rejections = true;
+
+    net.record_trace();
+    let packets = net.exec_op(&actors[0], 0).map_err(|_| "op refused")?;
+    net.run_packets_to_completion(packets);
+
+    let replayed: TestNet = net
+        .trace
+        .as_ref()
+        .ok_or("trace not recorded")?
+        .replay()
+        .map_err(|_| "replay diverged")?;
+
+    assert!(replayed.send_rejections);
+    for proc in replayed.procs.iter() {
+        assert_eq!(proc.reorder_buffer_limit, 2);
+        assert_eq!(proc.generation_lookahead, 3);
+        assert!(!proc.auto_catch_up);
+        assert_eq!(proc.rate_limiter.limits, limits);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_replay_reports_the_first_divergent_step() -> Result<(), &'static str> {
+    let net = record_run();
+    let seeds = net.seeds();
+    let mut trace = net.trace.ok_or("trace not recorded")?;
+
+    let step = trace
+        .steps
+        .iter()
+        .rposition(|step| matches!(step, Step::ExecOp { refused: None, .. }))
+        .ok_or("op not recorded")?;
+    if let Step::ExecOp { op, .. } = &mut trace.steps[step] {
+        *op += 100;
+    }
+
+    let divergence = trace
+        .replay::<TestDT>()
+        .err()
+        .ok_or("replay did not diverge")?;
+
+    assert_eq!(divergence.step, step, "{}", seeds);
+    assert_eq!(divergence.expected, trace.steps[step].sent(), "{}", seeds);
+    assert_eq!(
+        divergence.actual.len(),
+        divergence.expected.len(),
+        "{}",
+        seeds
+    );
+    assert_ne!(divergence.actual, divergence.expected, "{}", seeds);
+    Ok(())
+}
@@ -0,0 +1,181 @@
+#![cfg(feature = "tracing")]
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use brb::net::{Actor, Packet};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use common::{bootstrap_net, TestDT};
+
+type SpanFields = BTreeMap<String, String>;
+
+/// Records the fields of every span created while it's the default subscriber.
+#[derive(Debug, Default, Clone)]
+struct SpanRecorder {
+    spans: Arc<Mutex<Vec<SpanFields>>>,
+}
+
+impl SpanRecorder {
+    /// Runs f with this recorder as the default subscriber, returning the spans it created.
+    fn record(&self, f: impl FnOnce()) -> Vec<SpanFields> {
+        tracing::subscriber::with_default(self.clone(), f);
+        self.spans.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut SpanFields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for SpanRecorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let mut fields = SpanFields::new();
+        fields.insert("name".to_string(), attrs.metadata().name().to_string());
+        attrs.record(&mut FieldVisitor(&mut fields));
+        match self.spans.lock() {
+            Ok(mut spans) => {
+                spans.push(fields);
+                Id::from_u64(spans.len() as u64)
+            }
+            Err(_) => Id::from_u64(u64::MAX),
+        }
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Ok(mut spans) = self.spans.lock() {
+            if let Some(fields) = spans.get_mut(span.into_u64() as usize - 1) {
+                values.record(&mut FieldVisitor(fields));
+            }
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+/// true if span is for stage of the msg with the given dot at node.
+fn is_span(span: &SpanFields, node: &Actor, stage: &str, dot: (&Actor, u64)) -> bool {
+    span.get("node") == Some(&node.to_string())
+        && span.get("stage").map(String::as_str) == Some(stage)
+        && span.get("dot.actor") == Some(&dot.0.to_string())
+        && span.get("dot.counter") == Some(&dot.1.to_string())
+}
+
+#[test]
+fn test_msg_lifecycle_is_traced_by_dot_and_generation() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<TestDT>(3);
+    let (actor_a, actor_b) = (actors[0], actors[1]);
+
+    let spans = SpanRecorder::default().record(|| {
+        let packets = net
+            .proc_mut(&actor_a)
+            .and_then(|p| p.exec_op(1u8).ok())
+            .unwrap_or_default();
+        net.run_packets_to_completion(packets);
+    });
+    assert!(net.members_are_in_agreement());
+
+    for (node, stage) in [
+        (actor_a, "exec_op"),
+        (actor_b, "request_validation"),
+        (actor_a, "signed_validated"),
+        (actor_b, "proof_of_agreement"),
+        (actor_a, "delivered"),
+    ] {
+        let span = spans
+            .iter()
+            .find(|s| is_span(s, &node, stage, (&actor_a, 1)))
+            .ok_or("Missing span")?;
+        assert_eq!(span.get("name").map(String::as_str), Some("brb_msg"));
+        assert_eq!(span.get("gen").map(String::as_str), Some("0"));
+        assert_eq!(span.get("replayed").map(String::as_str), Some("false"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_replayed_and_caught_up_msgs_are_traced() -> Result<(), &'static str> {
+    let (mut net, actors) = bootstrap_net::<TestDT>(4);
+    let (actor_a, actor_c) = (actors[0], actors[2]);
+
+    // actor_c is cut off while actor_a gets two ops agreed on by the rest of the network.
+    let mut held_back: Vec<Packet<u8>> = Vec::new();
+    for op in [1u8, 2u8] {
+        let mut packets = net
+            .proc_mut(&actor_a)
+            .ok_or("No proc for actor_a")?
+            .exec_op(op)
+            .map_err(|_| "Failed to generate insert op")?;
+        while !packets.is_empty() {
+            let packet = packets.remove(0);
+            if packet.dest == actor_c {
+                held_back.push(packet);
+            } else {
+                packets.extend(net.deliver_packet(packet));
+            }
+        }
+    }
+    let mut proofs: Vec<_> = held_back
+        .into_iter()
+        .filter(|p| p.payload.is_proof_of_agreement())
+        .collect();
+    let proof_2 = proofs.pop().ok_or("Failed to pop proof")?;
+    let proof_1 = proofs.pop().ok_or("Failed to pop proof")?;
+
+    // The second proof is buffered, then replayed once the first one arrives.
+    let spans = SpanRecorder::default().record(|| {
+        net.deliver_packet(proof_2);
+        net.deliver_packet(proof_1);
+    });
+    let replayed: Vec<_> = spans
+        .iter()
+        .filter(|s| is_span(s, &actor_c, "proof_of_agreement", (&actor_a, 2)))
+        .filter_map(|s| s.get("replayed").map(String::as_str))
+        .collect();
+    assert_eq!(replayed, vec!["false", "true"]);
+
+    // Proofs resent to help a new proc catch up are traced under their own dot.
+    let actor_d = net.initialize_proc();
+    let anti_entropy = net
+        .proc(&actor_d)
+        .ok_or("No proc for actor_d")?
+        .anti_entropy(actor_a)
+        .map_err(|_| "Failed to generate anti-entropy")?;
+    let spans = SpanRecorder::default().record(|| {
+        net.deliver_packet(anti_entropy);
+    });
+    for counter in [1, 2] {
+        let span = spans
+            .iter()
+            .find(|s| is_span(s, &actor_a, "catch_up", (&actor_a, counter)))
+            .ok_or("Missing span for a resent proof")?;
+        assert_eq!(span.get("gen").map(String::as_str), Some("0"));
+    }
+
+    Ok(())
+}